@@ -21,6 +21,16 @@ fn level_off(c: &mut Criterion) {
     }));
 }
 
+fn directive_off(c: &mut Criterion) {
+    let mut logger = logkit::Logger::nop();
+    let _ = logger.directives("benchmark::db=trace,info");
+    logkit::set_default_logger(logger);
+
+    c.bench_function("directive_off", |b| b.iter(|| {
+        trace!();
+    }));
+}
+
 fn msg_only(c: &mut Criterion) {
     let logger = logkit::Logger::nop();
     logkit::set_default_logger(logger);
@@ -98,6 +108,7 @@ criterion_group!(
     benches,
     empty_log,
     level_off,
+    directive_off,
     msg_only,
     msg_format,
     fields_only,
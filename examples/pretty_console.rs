@@ -0,0 +1,20 @@
+#[macro_use] extern crate logkit;
+
+fn main() -> anyhow::Result<()> {
+    let console = logkit::ConsoleTarget::stdout();
+    console.format.highlight(logkit::Highlight::pattern("msg", r"\d+\.\d+\.\d+\.\d+", logkit::ANSI_CYAN)?);
+    console.format.highlight(logkit::Highlight::field("commit", logkit::ANSI_MAGENTA));
+
+    let mut logger = logkit::Logger::new(None);
+    logger.mount(logkit::TimePlugin::from_millis());
+    logger.route(console);
+    logkit::set_default_logger(logger);
+
+    trace!("hello, this is a trace log");
+    debug!("hello, this is a debug log");
+    info!(version = "0.1.0", commit = "3291cc60"; "this is a log with two string fields");
+    warn!(address = "127.0.0.1", port = 3000; "this is a log with a string and a numeric field");
+    error!("this is a log with\na message of\nmultiple lines from 127.0.0.1");
+
+    Ok(())
+}
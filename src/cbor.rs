@@ -0,0 +1,341 @@
+//! CBOR encoding of records
+use super::format::*;
+use super::record::*;
+use super::value::*;
+
+/// Output CBOR
+///
+/// Each record is encoded as a map of its fields. The `time` field is encoded as an epoch-based
+/// date/time (tag 1), and integers beyond 64 bits as bignums (tag 2 and 3). Records are written as
+/// a CBOR sequence (RFC 8742), or each is prefixed with its length in 4 big-endian bytes. Owned
+/// values appended with `Record::append_value` are encoded straight from their typed values, other
+/// fields are parsed back from their JSON first.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("time", &"2024-01-03T11:01:00+08:00");
+/// record.append("big", &u128::MAX);
+/// record.finish();
+///
+/// let data = record.render(&logkit::CborFormat::prefixed()).to_vec();
+/// assert_eq!(&data[..4], &[0, 0, 0, 34]);
+///
+/// let mut decoder = logkit::CborDecoder::prefixed(&data);
+/// assert_eq!(decoder.next().unwrap().unwrap(), logkit::Value::Object(vec![
+///     ("time".into(), logkit::Value::Str("2024-01-03T03:01:00Z".into())),
+///     ("big".into(), logkit::Value::BigInt(u128::MAX.to_string().into())),
+/// ]));
+/// assert!(decoder.next().is_none());
+/// ```
+pub struct CborFormat {
+    /// prefix each record with its length
+    pub prefixed: bool,
+}
+
+impl CborFormat {
+    /// Write records as a CBOR sequence
+    pub fn sequence() -> Self {
+        Self {prefixed: false}
+    }
+
+    /// Prefix each record with its length
+    pub fn prefixed() -> Self {
+        Self {prefixed: true}
+    }
+}
+
+impl Format for CborFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let start = buf.len();
+
+        if self.prefixed {
+            buf.extend_from_slice(&[0; 4]);
+        }
+
+        cbor_head(buf, 5, record.fields().count() as u64);
+
+        for field in record.fields() {
+            let key = field.key();
+            let value = field.value();
+
+            cbor_encode(&Value::Str(key.as_ref().into()), buf);
+
+            match (key.as_ref(), value.as_str().and_then(|val| chrono::DateTime::parse_from_rfc3339(val).ok())) {
+                ("time", Some(time)) => {
+                    cbor_head(buf, 6, 1);
+
+                    match time.timestamp_subsec_nanos() {
+                        0 => cbor_encode(&Value::Int(time.timestamp()), buf),
+                        nanos => cbor_encode(&Value::Float(time.timestamp() as f64 + nanos as f64 / 1e9), buf),
+                    }
+                }
+                _ => cbor_encode(&value, buf),
+            }
+        }
+
+        if self.prefixed {
+            let len = (buf.len() - start - 4) as u32;
+            buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+        }
+    }
+}
+
+/// Encode a value to CBOR
+///
+/// ```
+/// let mut buf = vec![];
+/// logkit::cbor_encode(&logkit::Value::Array(vec![logkit::Value::Int(-1), logkit::Value::Null, logkit::Value::Str("a".into())]), &mut buf);
+/// assert_eq!(buf, b"\x83\x20\xf6\x61a");
+/// ```
+pub fn cbor_encode(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(0xf6),
+        Value::Bool(val) => buf.push(if *val { 0xf5 } else { 0xf4 }),
+        Value::Uint(val) => cbor_head(buf, 0, *val),
+        Value::Int(val) if *val >= 0 => cbor_head(buf, 0, *val as u64),
+        Value::Int(val) => cbor_head(buf, 1, !*val as u64),
+        Value::Float(val) => { buf.push(0xfb); buf.extend_from_slice(&val.to_be_bytes()); }
+        Value::BigInt(val) => {
+            let (tag, digits) = match val.strip_prefix('-') {
+                Some(digits) => (3, digits),
+                None => (2, val.as_ref()),
+            };
+
+            match digits.parse::<u128>().ok().and_then(|num| if tag == 3 { num.checked_sub(1) } else { Some(num) }) {
+                Some(num) => {
+                    let bytes = num.to_be_bytes();
+                    let skip = bytes.iter().take_while(|ch| **ch == 0).count();
+
+                    cbor_head(buf, 6, tag);
+                    cbor_head(buf, 2, (bytes.len() - skip) as u64);
+                    buf.extend_from_slice(&bytes[skip..]);
+                }
+                None => cbor_encode(&Value::Str(val.as_ref().into()), buf),
+            }
+        }
+        Value::Str(val) => {
+            cbor_head(buf, 3, val.len() as u64);
+            buf.extend_from_slice(val.as_bytes());
+        }
+        Value::Array(val) => {
+            cbor_head(buf, 4, val.len() as u64);
+            val.iter().for_each(|item| cbor_encode(item, buf));
+        }
+        Value::Object(val) => {
+            cbor_head(buf, 5, val.len() as u64);
+
+            for (key, item) in val {
+                cbor_head(buf, 3, key.len() as u64);
+                buf.extend_from_slice(key.as_bytes());
+                cbor_encode(item, buf);
+            }
+        }
+        Value::Raw(_) => cbor_encode(&value.parsed(), buf),
+    }
+}
+
+#[inline]
+fn cbor_head(buf: &mut Vec<u8>, major: u8, val: u64) {
+    let major = major << 5;
+
+    match val {
+        0..=23 => buf.push(major | val as u8),
+        24..=0xff => { buf.push(major | 24); buf.push(val as u8); }
+        0x100..=0xffff => { buf.push(major | 25); buf.extend_from_slice(&(val as u16).to_be_bytes()); }
+        0x10000..=0xffff_ffff => { buf.push(major | 26); buf.extend_from_slice(&(val as u32).to_be_bytes()); }
+        _ => { buf.push(major | 27); buf.extend_from_slice(&val.to_be_bytes()); }
+    }
+}
+
+/// Read values back from CBOR
+///
+/// Iterates over the values written by CborFormat. Date/times (tag 1) are decoded to RFC 3339
+/// strings in UTC, and bignums (tag 2 and 3) to big integers. The iteration stops after an error.
+///
+/// ```
+/// let data = b"\xa1\x63msg\x62hi\xa1\x63msg\x63bye";
+/// let values: Vec<_> = logkit::CborDecoder::sequence(data).collect::<anyhow::Result<_>>().unwrap();
+/// assert_eq!(values.len(), 2);
+/// assert_eq!(values[1], logkit::Value::Object(vec![("msg".into(), logkit::Value::Str("bye".into()))]));
+///
+/// assert!(logkit::CborDecoder::sequence(b"\xa1\x63msg").next().unwrap().is_err());
+/// ```
+pub struct CborDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    prefixed: bool,
+}
+
+impl<'a> CborDecoder<'a> {
+    /// Read a CBOR sequence
+    pub fn sequence(data: &'a [u8]) -> Self {
+        Self {data, pos: 0, prefixed: false}
+    }
+
+    /// Read records prefixed with their length
+    pub fn prefixed(data: &'a [u8]) -> Self {
+        Self {data, pos: 0, prefixed: true}
+    }
+
+    fn take(&mut self, len: u64) -> anyhow::Result<&'a [u8]> {
+        let end = usize::try_from(len).ok().and_then(|len| self.pos.checked_add(len));
+
+        match end.and_then(|end| self.data.get(self.pos..end)) {
+            Some(val) => { self.pos += val.len(); Ok(val) }
+            None => anyhow::bail!("unexpected end of cbor data"),
+        }
+    }
+
+    fn uint(&mut self, len: u64) -> anyhow::Result<u64> {
+        Ok(self.take(len)?.iter().fold(0, |acc, ch| acc << 8 | *ch as u64))
+    }
+
+    fn head(&mut self) -> anyhow::Result<(u8, u8, u64)> {
+        let head = self.take(1)?[0];
+        let (major, info) = (head >> 5, head & 0x1f);
+
+        let arg = match info {
+            0..=23 => info as u64,
+            24 => self.uint(1)?,
+            25 => self.uint(2)?,
+            26 => self.uint(4)?,
+            27 => self.uint(8)?,
+            _ => anyhow::bail!("unsupported cbor header 0x{:02x}", head),
+        };
+
+        Ok((major, info, arg))
+    }
+
+    fn value(&mut self, depth: usize) -> anyhow::Result<Value<'a>> {
+        if depth > CBOR_DEPTH {
+            anyhow::bail!("cbor data nested too deep");
+        }
+
+        let (major, info, arg) = self.head()?;
+
+        Ok(match major {
+            0 => Value::Uint(arg),
+            1 => match i64::try_from(arg) {
+                Ok(val) => Value::Int(-1 - val),
+                Err(_) => Value::BigInt(format!("-{}", arg as u128 + 1).into()),
+            },
+            2 | 3 => Value::Str(String::from_utf8_lossy(self.take(arg)?)),
+            4 => {
+                let mut items = vec![];
+
+                for _ in 0..arg {
+                    items.push(self.value(depth + 1)?);
+                }
+
+                Value::Array(items)
+            }
+            5 => {
+                let mut items = vec![];
+
+                for _ in 0..arg {
+                    let key = match self.value(depth + 1)? {
+                        Value::Str(key) => key,
+                        _ => anyhow::bail!("cbor map key is not a string"),
+                    };
+
+                    items.push((key, self.value(depth + 1)?));
+                }
+
+                Value::Object(items)
+            }
+            6 => self.tagged(arg, depth)?,
+            _ => match (info, arg) {
+                (20, _) => Value::Bool(false),
+                (21, _) => Value::Bool(true),
+                (22 | 23, _) => Value::Null,
+                (25, val) => Value::Float(cbor_half(val as u16)),
+                (26, val) => Value::Float(f32::from_bits(val as u32) as f64),
+                (27, val) => Value::Float(f64::from_bits(val)),
+                _ => anyhow::bail!("unsupported cbor simple value {}", arg),
+            },
+        })
+    }
+
+    fn tagged(&mut self, tag: u64, depth: usize) -> anyhow::Result<Value<'a>> {
+        match tag {
+            1 => {
+                let secs = match self.value(depth + 1)? {
+                    Value::Uint(val) => val as f64,
+                    Value::Int(val) => val as f64,
+                    Value::Float(val) => val,
+                    _ => anyhow::bail!("cbor date/time is not a number"),
+                };
+
+                let nanos = (secs.rem_euclid(1.0) * 1e9).round() as u32;
+
+                match chrono::DateTime::from_timestamp(secs.floor() as i64, nanos.min(999_999_999)) {
+                    Some(time) => Ok(Value::Str(time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true).into())),
+                    None => anyhow::bail!("cbor date/time out of range"),
+                }
+            }
+            2 | 3 => {
+                let (major, _, len) = self.head()?;
+
+                if major != 2 || len > 16 {
+                    anyhow::bail!("unsupported cbor bignum");
+                }
+
+                let num = self.take(len)?.iter().fold(0u128, |acc, ch| acc << 8 | *ch as u128);
+
+                match tag {
+                    2 => Ok(Value::BigInt(num.to_string().into())),
+                    _ => match num.checked_add(1) {
+                        Some(num) => Ok(Value::BigInt(format!("-{}", num).into())),
+                        None => anyhow::bail!("unsupported cbor bignum"),
+                    },
+                }
+            }
+            _ => self.value(depth + 1),
+        }
+    }
+}
+
+impl<'a> Iterator for CborDecoder<'a> {
+    type Item = anyhow::Result<Value<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let value = match self.prefixed {
+            true => self.uint(4).and_then(|len| {
+                let frame = self.take(len)?;
+                let mut inner = CborDecoder::sequence(frame);
+                let value = inner.value(0)?;
+
+                match inner.pos == frame.len() {
+                    true => Ok(value),
+                    false => anyhow::bail!("trailing bytes in cbor frame"),
+                }
+            }),
+            false => self.value(0),
+        };
+
+        if value.is_err() {
+            self.pos = self.data.len();
+        }
+
+        Some(value)
+    }
+}
+
+const CBOR_DEPTH: usize = 128;
+
+fn cbor_half(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (bits >> 10) & 0x1f;
+    let frac = (bits & 0x3ff) as f64;
+
+    sign * match exp {
+        0 => frac * 2f64.powi(-24),
+        31 if frac == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + frac / 1024.0) * 2f64.powi(exp as i32 - 15),
+    }
+}
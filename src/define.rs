@@ -3,6 +3,7 @@ pub(crate) use std::any::Any;
 pub(crate) use std::io::Write;
 pub(crate) use std::path::Path;
 pub(crate) use std::sync::Mutex;
+pub(crate) use std::sync::RwLock;
 
 /// Log Level
 /// 
@@ -0,0 +1,1599 @@
+//! Format trait and built-in output formats
+use super::define::*;
+use super::record::*;
+use super::source::*;
+use super::value::*;
+use std::borrow::Cow;
+
+/// The Format Trait
+///
+/// A format renders a finished record into the bytes a target writes. Targets use the record's
+/// JSON buffer unless a format is set for them, so a JSON file and a human-readable console can be
+/// fed by the same logger. Formats read the structured fields of a record instead of its buffer.
+///
+/// ```
+/// pub struct KeysFormat;
+///
+/// impl logkit::Format for KeysFormat {
+///     fn format(&self, record: &logkit::Record, buf: &mut Vec<u8>) {
+///         for field in record.fields() {
+///             buf.extend_from_slice(field.key().as_bytes());
+///             buf.push(b' ');
+///         }
+///
+///         buf.push(b'\n');
+///     }
+/// }
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+/// record.append("pid", &12345);
+/// record.append("msg", &"hello");
+/// record.finish();
+/// assert_eq!(record.render(&KeysFormat), b"pid msg \n");
+/// ```
+pub trait Format: AnyFormat + Send + Sync + 'static {
+    /// Format a finished record and append the output to buf
+    fn format(&self, record: &Record, buf: &mut Vec<u8>);
+
+    /// Append the header written at the beginning of new output, like the column names of a table
+    #[inline]
+    fn header(&self, _buf: &mut Vec<u8>) {}
+}
+
+/// Any Support
+pub trait AnyFormat: Any {
+    /// Treat object as any
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any> AnyFormat for T {
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Output JSON, the same as targets without a format
+///
+/// ```json,no_run
+/// {"level":"info","msg":"hello"}
+/// ```
+pub struct JsonFormat;
+
+impl Format for JsonFormat {
+    #[inline]
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(record.buffer());
+    }
+}
+
+/// Output logfmt
+///
+/// Values are quoted only when necessary, and nested objects and arrays are flattened into keys
+/// joined by dots.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("level", &"info");
+/// record.append("msg", &"say \"hi\"");
+/// record.append("port", &3000);
+/// record.append("tags", &vec!["a", "b c"]);
+/// record.finish();
+/// assert_eq!(String::from_utf8_lossy(record.render(&logkit::LogfmtFormat)), "level=info msg=\"say \\\"hi\\\"\" port=3000 tags.0=a tags.1=\"b c\"\n");
+/// ```
+pub struct LogfmtFormat;
+
+impl Format for LogfmtFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let start = buf.len();
+
+        for field in record.fields() {
+            field.value().flatten(&field.key(), |key, val| {
+                logfmt_key(buf, key);
+                buf.push(b'=');
+                logfmt_value(buf, val);
+                buf.push(b' ');
+            });
+        }
+
+        match buf[start..].last_mut() {
+            Some(val) if *val == b' ' => *val = b'\n',
+            _ => buf.push(b'\n'),
+        }
+    }
+}
+
+/// Append a logfmt key, replacing characters that would break the pair
+///
+/// ```
+/// let mut buf = vec![];
+/// logkit::format::logfmt_key(&mut buf, "user id=\"1\"");
+/// assert_eq!(buf, b"user_id__1_");
+/// ```
+pub fn logfmt_key(buf: &mut Vec<u8>, key: &str) {
+    if key.is_empty() {
+        buf.push(b'_');
+        return;
+    }
+
+    for ch in key.chars() {
+        match ch {
+            ' ' | '=' | '"' => buf.push(b'_'),
+            _ if ch.is_control() => buf.push(b'_'),
+            _ => buf.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+/// Append a logfmt value, flattened values are written as is
+///
+/// ```
+/// let mut buf = vec![];
+/// logkit::format::logfmt_value(&mut buf, &logkit::Value::Str("".into()));
+/// logkit::format::logfmt_value(&mut buf, &logkit::Value::Str("a=b".into()));
+/// logkit::format::logfmt_value(&mut buf, &logkit::Value::Str("line\nbreak".into()));
+/// assert_eq!(buf, b"\"\"\"a=b\"\"line\\nbreak\"");
+/// ```
+pub fn logfmt_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Str(val) => logfmt_str(buf, val),
+        Value::Array(_) => buf.extend_from_slice(b"[]"),
+        Value::Object(_) => buf.extend_from_slice(b"{}"),
+        _ => value.encode(buf),
+    }
+}
+
+fn logfmt_str(buf: &mut Vec<u8>, val: &str) {
+    if !logfmt_quote(val) {
+        buf.extend_from_slice(val.as_bytes());
+        return;
+    }
+
+    buf.push(b'"');
+    logfmt_escape(buf, val);
+    buf.push(b'"');
+}
+
+#[inline]
+fn logfmt_quote(val: &str) -> bool {
+    val.is_empty() || val.chars().any(|ch| matches!(ch, ' ' | '=' | '"' | '\\') || ch.is_control())
+}
+
+fn logfmt_escape(buf: &mut Vec<u8>, val: &str) {
+    for ch in val.chars() {
+        match ch {
+            '"' => buf.extend_from_slice(b"\\\""),
+            '\\' => buf.extend_from_slice(b"\\\\"),
+            '\n' => buf.extend_from_slice(b"\\n"),
+            '\r' => buf.extend_from_slice(b"\\r"),
+            '\t' => buf.extend_from_slice(b"\\t"),
+            _ if ch.is_control() => buf.extend_from_slice(format!("\\u{:04x}", ch as u32).as_bytes()),
+            _ => buf.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+/// Output human-readable text
+///
+/// Records are rendered as `time LEVEL msg key=value...`. The time and message come from the
+/// `time` and `msg` fields, while the level is taken from the record itself. Lines following the
+/// first line of a message are indented to line up with it. With colors enabled, levels are
+/// painted with ANSI escape codes, keys are dimmed, and highlight rules are applied.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("time", &"2024-01-03T11:01:00+08:00");
+/// record.append("msg", &"hello\nworld");
+/// record.append("port", &3000);
+/// record.finish();
+///
+/// let text = record.render(&logkit::TextFormat::new(false));
+/// assert_eq!(String::from_utf8_lossy(text), "2024-01-03T11:01:00+08:00 INFO  hello\n                                world port=3000\n");
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("time", &"now\r");
+/// record.append("msg", &"clear\x1b[2J\tscreen");
+/// record.finish();
+///
+/// let text = record.render(&logkit::TextFormat::new(false));
+/// assert_eq!(String::from_utf8_lossy(text), "now\\r INFO  clear\\u001b[2J\\tscreen\n");
+/// ```
+pub struct TextFormat {
+    /// colorize output with ANSI escape codes
+    pub color: bool,
+
+    /// highlight rules, applied only with colors
+    pub rules: RwLock<Vec<Highlight>>,
+}
+
+impl TextFormat {
+    /// Create a TextFormat with or without colors
+    pub fn new(color: bool) -> Self {
+        Self {color, rules: RwLock::new(vec![])}
+    }
+
+    /// Add a highlight rule
+    ///
+    /// Rules can be changed at any time, even after the format is in use by a logger.
+    ///
+    /// ```
+    /// fn main() -> anyhow::Result<()> {
+    ///     let format = logkit::TextFormat::new(true);
+    ///     format.highlight(logkit::Highlight::field("user_id", logkit::ANSI_CYAN));
+    ///     format.highlight(logkit::Highlight::pattern("msg", r"\d+\.\d+\.\d+\.\d+", logkit::ANSI_YELLOW)?);
+    ///     format.highlight(logkit::Highlight::record("status", "500", logkit::ANSI_RED));
+    ///
+    ///     let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+    ///     record.append("msg", &"request from 127.0.0.1");
+    ///     record.append("user_id", &42);
+    ///     record.finish();
+    ///
+    ///     let text = String::from_utf8_lossy(record.render(&format)).to_string();
+    ///     assert!(text.contains("request from \x1b[33m127.0.0.1\x1b[0m"));
+    ///     assert!(text.contains("\x1b[36m42\x1b[0m"));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn highlight(&self, rule: Highlight) -> &Self {
+        if let Ok(mut rules) = self.rules.write() {
+            rules.push(rule);
+        }
+
+        self
+    }
+
+    /// Remove highlight rules
+    ///
+    /// ```
+    /// let format = logkit::TextFormat::new(true);
+    /// format.highlight(logkit::Highlight::field("user_id", logkit::ANSI_CYAN));
+    /// format.unhighlight(|rule| matches!(rule, logkit::Highlight::Field {key, ..} if key == "user_id"));
+    /// assert_eq!(format.rules.read().unwrap().len(), 0);
+    /// ```
+    pub fn unhighlight(&self, del: impl Fn(&Highlight) -> bool) -> &Self {
+        if let Ok(mut rules) = self.rules.write() {
+            rules.retain(|rule| !del(rule));
+        }
+
+        self
+    }
+}
+
+impl Format for TextFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let guard = match self.color {
+            true => self.rules.read().ok(),
+            false => None,
+        };
+
+        let rules = guard.as_deref().map_or(&[][..], |val| val.as_slice());
+        let emphasize = rules.iter().any(|rule| matches!(rule, Highlight::Record {..}));
+
+        let mut time = None;
+        let mut msg = None;
+        let mut emphasis = None;
+
+        for field in record.fields() {
+            let key = field.key();
+
+            match key.as_ref() {
+                "time" if time.is_none() => time = Some(field.value()),
+                "msg" if msg.is_none() => msg = Some(field.value()),
+                _ => {}
+            }
+
+            if emphasize && emphasis.is_none() {
+                field.value().flatten(&key, |key, val| {
+                    let text = text_raw(val);
+
+                    for rule in rules {
+                        match rule {
+                            Highlight::Record {key: name, value, style} if name == key && *value == text => emphasis = Some(*style),
+                            _ => {}
+                        }
+                    }
+                });
+            }
+        }
+
+        let mut indent = 0;
+
+        if let Some(time) = time {
+            let mut text = vec![];
+            text_value(&mut text, &time);
+            indent += String::from_utf8_lossy(&text).chars().count() + 1;
+            text_paint(buf, self.color, ANSI_DIM, |buf| buf.extend_from_slice(&text));
+            buf.push(b' ');
+        }
+
+        let level = match level_to_str(record.level()) {
+            Some(val) => format!("{:<5}", val.to_uppercase()),
+            None => format!("{:<5}", record.level()),
+        };
+
+        text_paint(buf, self.color, level_to_ansi(record.level()), |buf| buf.extend_from_slice(level.as_bytes()));
+        indent += level.len() + 1;
+
+        if let Some(msg) = msg {
+            for (i, line) in text_raw(&msg).split('\n').enumerate() {
+                match i {
+                    0 => buf.push(b' '),
+                    _ => { buf.push(b'\n'); buf.resize(buf.len() + indent, b' '); }
+                }
+
+                text_highlight(buf, "msg", line, text_escape, rules, emphasis);
+            }
+        }
+
+        for field in record.fields() {
+            let key = field.key();
+
+            if matches!(key.as_ref(), "time" | "level" | "msg") {
+                continue;
+            }
+
+            field.value().flatten(&key, |key, val| {
+                buf.push(b' ');
+                text_paint(buf, self.color, ANSI_DIM, |buf| { logfmt_key(buf, key); buf.push(b'='); });
+
+                match val {
+                    Value::Str(val) if logfmt_quote(val) => {
+                        buf.push(b'"');
+                        text_highlight(buf, key, val, logfmt_escape, rules, None);
+                        buf.push(b'"');
+                    }
+                    Value::Str(_) | Value::Array(_) | Value::Object(_) => {
+                        let mut text = vec![];
+                        logfmt_value(&mut text, val);
+                        text_highlight(buf, key, &String::from_utf8_lossy(&text), text_plain, rules, None);
+                    }
+                    _ => text_highlight(buf, key, &text_raw(val), text_plain, rules, None),
+                }
+            });
+        }
+
+        buf.push(b'\n');
+    }
+}
+
+/// Highlight Rule
+///
+/// Rules are used by TextFormat to paint parts of the console output. Keys of nested fields are
+/// joined by dots, like `http.status`. Patterns and values match the raw strings, before they are
+/// quoted and escaped for the output.
+///
+/// ```
+/// fn main() -> anyhow::Result<()> {
+///     let format = logkit::TextFormat::new(true);
+///     format.highlight(logkit::Highlight::pattern("query", r#""\w+""#, logkit::ANSI_YELLOW)?);
+///
+///     let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+///     record.append("query", &"say \"hi\"");
+///     record.finish();
+///
+///     let text = String::from_utf8_lossy(record.render(&format)).to_string();
+///     assert!(text.contains("\"say \x1b[33m\\\"hi\\\"\x1b[0m\""));
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub enum Highlight {
+    /// Paint the values of a field
+    Field {
+        /// field key
+        key: String,
+
+        /// ANSI escape code
+        style: &'static str,
+    },
+
+    /// Paint the parts of a field's value that match a pattern
+    Pattern {
+        /// field key, `msg` for the message
+        key: String,
+
+        /// pattern to match
+        regex: regex::Regex,
+
+        /// ANSI escape code
+        style: &'static str,
+    },
+
+    /// Paint the message of records in which a field has a specific value
+    Record {
+        /// field key
+        key: String,
+
+        /// field value, strings are compared without quotes and others in JSON
+        value: String,
+
+        /// ANSI escape code
+        style: &'static str,
+    },
+}
+
+impl Highlight {
+    /// Paint the values of a field
+    pub fn field(key: impl Into<String>, style: &'static str) -> Self {
+        Self::Field {key: key.into(), style}
+    }
+
+    /// Paint the parts of a field's value that match a regular expression
+    pub fn pattern(key: impl Into<String>, pattern: &str, style: &'static str) -> anyhow::Result<Self> {
+        Ok(Self::Pattern {key: key.into(), regex: regex::Regex::new(pattern)?, style})
+    }
+
+    /// Paint the message of records in which a field has a specific value
+    pub fn record(key: impl Into<String>, value: impl Into<String>, style: &'static str) -> Self {
+        Self::Record {key: key.into(), value: value.into(), style}
+    }
+}
+
+/// Reset all styles
+pub const ANSI_RESET: &str = "\x1b[0m";
+/// Bold text
+pub const ANSI_BOLD: &str = "\x1b[1m";
+/// Dimmed text
+pub const ANSI_DIM: &str = "\x1b[2m";
+/// Red text
+pub const ANSI_RED: &str = "\x1b[31m";
+/// Green text
+pub const ANSI_GREEN: &str = "\x1b[32m";
+/// Yellow text
+pub const ANSI_YELLOW: &str = "\x1b[33m";
+/// Blue text
+pub const ANSI_BLUE: &str = "\x1b[34m";
+/// Magenta text
+pub const ANSI_MAGENTA: &str = "\x1b[35m";
+/// Cyan text
+pub const ANSI_CYAN: &str = "\x1b[36m";
+/// Gray text
+pub const ANSI_GRAY: &str = "\x1b[90m";
+
+/// ANSI color of a level
+///
+/// ```
+/// assert_eq!(logkit::format::level_to_ansi(logkit::LEVEL_ERROR), logkit::ANSI_RED);
+/// ```
+pub fn level_to_ansi(level: Level) -> &'static str {
+    match level {
+        LEVEL_TRACE => ANSI_GRAY,
+        LEVEL_DEBUG => ANSI_BLUE,
+        LEVEL_INFO => ANSI_GREEN,
+        LEVEL_WARN => ANSI_YELLOW,
+        LEVEL_ERROR => ANSI_RED,
+        _ => ANSI_MAGENTA,
+    }
+}
+
+// rules match the raw text, which is escaped piece by piece after the color codes are placed
+fn text_highlight(buf: &mut Vec<u8>, key: &str, text: &str, escape: fn(&mut Vec<u8>, &str), rules: &[Highlight], base: Option<&'static str>) {
+    let base = rules.iter().rev().find_map(|rule| match rule {
+        Highlight::Field {key: name, style} if name == key => Some(*style),
+        _ => None,
+    }).or(base);
+
+    if rules.is_empty() && base.is_none() {
+        escape(buf, text);
+        return;
+    }
+
+    let mut found = vec![];
+
+    for rule in rules {
+        match rule {
+            Highlight::Pattern {key: name, regex, style} if name == key => {
+                found.extend(regex.find_iter(text).map(|m| (m.start(), m.end(), *style)));
+            }
+            _ => {}
+        }
+    }
+
+    found.sort_by_key(|m| m.0);
+
+    let mut pos = 0;
+
+    if let Some(base) = base {
+        buf.extend_from_slice(base.as_bytes());
+    }
+
+    for (beg, end, style) in found {
+        if beg < pos {
+            continue; // overlapped
+        }
+
+        escape(buf, &text[pos..beg]);
+        buf.extend_from_slice(style.as_bytes());
+        escape(buf, &text[beg..end]);
+        buf.extend_from_slice(ANSI_RESET.as_bytes());
+
+        if let Some(base) = base {
+            buf.extend_from_slice(base.as_bytes());
+        }
+
+        pos = end;
+    }
+
+    escape(buf, &text[pos..]);
+
+    if base.is_some() {
+        buf.extend_from_slice(ANSI_RESET.as_bytes());
+    }
+}
+
+#[inline]
+fn text_paint(buf: &mut Vec<u8>, color: bool, code: &str, body: impl FnOnce(&mut Vec<u8>)) {
+    if color {
+        buf.extend_from_slice(code.as_bytes());
+    }
+
+    body(buf);
+
+    if color {
+        buf.extend_from_slice(ANSI_RESET.as_bytes());
+    }
+}
+
+// strings as they are, other values as JSON
+#[inline]
+fn text_raw<'a>(value: &'a Value) -> Cow<'a, str> {
+    match value {
+        Value::Str(val) => Cow::Borrowed(val),
+        other => {
+            let mut buf = vec![];
+            other.encode(&mut buf);
+            Cow::Owned(String::from_utf8_lossy(&buf).into_owned())
+        }
+    }
+}
+
+#[inline]
+fn text_plain(buf: &mut Vec<u8>, val: &str) {
+    buf.extend_from_slice(val.as_bytes());
+}
+
+// strings are written as is except control characters, other values as JSON
+#[inline]
+fn text_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Str(val) => text_escape(buf, val),
+        other => other.encode(buf),
+    }
+}
+
+// escape control characters except line breaks, so messages can't inject terminal sequences
+fn text_escape(buf: &mut Vec<u8>, val: &str) {
+    for ch in val.chars() {
+        match ch {
+            '\n' => buf.push(b'\n'),
+            '\r' => buf.extend_from_slice(b"\\r"),
+            '\t' => buf.extend_from_slice(b"\\t"),
+            _ if ch.is_control() => buf.extend_from_slice(format!("\\u{:04x}", ch as u32).as_bytes()),
+            _ => buf.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+/// Output text with a template
+///
+/// The template is parsed once at construction. Placeholders are written in braces, and literal
+/// braces are escaped by doubling them.
+///
+/// - `{time}` the `time` field, or the record's time if it's absent, `{time:%H:%M:%S%.3f}` to
+///   reformat it with strftime specifiers
+/// - `{level}` the record's level
+/// - `{src}` the `src` field, or the source file and line if it's absent
+/// - `{fields}` all fields not used elsewhere in the template, as `key=value` pairs
+/// - `{name}` any other field, e.g. `{msg}`
+///
+/// Except for a formatted time, placeholders accept a spec like `{level:>5}` or `{msg:-^20.10}`,
+/// with an optional fill character, an alignment (`<`, `>` or `^`), a minimum width, and a maximum
+/// width after a dot to truncate long values. Trailing spaces are trimmed from each line, except
+/// the padding of placeholders.
+///
+/// ```
+/// fn main() -> anyhow::Result<()> {
+///     let format = logkit::TemplateFormat::new("{time:%H:%M:%S} [{level:>5}] {msg:.10} {fields}")?;
+///
+///     let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+///     record.append("time", &"2024-01-03T11:01:00.123+08:00");
+///     record.append("msg", &"a long message to truncate");
+///     record.append("port", &3000);
+///     record.finish();
+///
+///     assert_eq!(String::from_utf8_lossy(record.render(&format)), "11:01:00 [ info] a long mes port=3000\n");
+///
+///     let format = logkit::TemplateFormat::new("{time} {user}")?;
+///     let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+///     record.append("time", &"now \x1b[2J");
+///     record.append("user", &"bob\x1b[0m");
+///     record.finish();
+///     assert_eq!(String::from_utf8_lossy(record.render(&format)), "now \\u001b[2J bob\\u001b[0m\n");
+///
+///     let format = logkit::TemplateFormat::new("{src} {fields} \n{msg:<8}")?;
+///     let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+///     record.append("src", &"src/main.rs:5:41");
+///     record.append("msg", &"hello");
+///     record.finish();
+///     assert_eq!(String::from_utf8_lossy(record.render(&format)), "src/main.rs:5:41\nhello   \n");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct TemplateFormat {
+    parts: Vec<TemplatePart>,
+    taken: Vec<String>, // keys excluded from `{fields}`
+}
+
+enum TemplatePart {
+    Text(String),
+    Time(Vec<chrono::format::Item<'static>>),
+    Level(TemplateSpec),
+    Source(TemplateSpec),
+    Fields(TemplateSpec),
+    Field(String, TemplateSpec),
+}
+
+struct TemplateSpec {
+    fill: char,
+    align: char,
+    width: usize,
+    limit: Option<usize>,
+}
+
+impl TemplateFormat {
+    /// Parse a template
+    ///
+    /// ```
+    /// assert!(logkit::TemplateFormat::new("{{{msg}}}").is_ok());
+    /// assert!(logkit::TemplateFormat::new("{msg").is_err());
+    /// assert!(logkit::TemplateFormat::new("{msg:10x}").is_err());
+    /// ```
+    pub fn new(template: &str) -> anyhow::Result<Self> {
+        let mut parts = vec![];
+        let mut taken = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.peek() == Some(&'{') => { chars.next(); text.push('{'); }
+                '}' if chars.peek() == Some(&'}') => { chars.next(); text.push('}'); }
+                '}' => anyhow::bail!("unmatched '}}' in template"),
+                '{' => {
+                    let mut inner = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(val) => inner.push(val),
+                            None => anyhow::bail!("unclosed placeholder in template"),
+                        }
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+
+                    let (name, spec) = inner.split_once(':').unwrap_or((inner.as_str(), ""));
+
+                    parts.push(match name {
+                        "time" => TemplatePart::Time(chrono::format::StrftimeItems::new(spec).parse_to_owned()?),
+                        "level" => TemplatePart::Level(TemplateSpec::new(spec)?),
+                        "src" => TemplatePart::Source(TemplateSpec::new(spec)?),
+                        "fields" => TemplatePart::Fields(TemplateSpec::new(spec)?),
+                        _ => TemplatePart::Field(name.to_string(), TemplateSpec::new(spec)?),
+                    });
+
+                    if name != "fields" {
+                        taken.push(name.to_string());
+                    }
+                }
+                _ => text.push(ch),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(Self {parts, taken})
+    }
+}
+
+impl Format for TemplateFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        use std::fmt::Write as _;
+
+        let mut floor = buf.len(); // trailing spaces before it are kept
+        let mut text = String::new();
+
+        for part in &self.parts {
+            text.clear();
+
+            match part {
+                TemplatePart::Text(val) => {
+                    for (i, line) in val.split('\n').enumerate() {
+                        if i > 0 {
+                            template_trim(buf, floor);
+                            buf.push(b'\n');
+                            floor = buf.len();
+                        }
+
+                        buf.extend_from_slice(line.as_bytes());
+                    }
+                }
+                TemplatePart::Time(items) => {
+                    let field = record.fields().find(|field| field.key() == "time").map(|field| field.value());
+                    let value = field.as_ref().and_then(|val| val.as_str());
+
+                    match (value, items.is_empty()) {
+                        (Some(val), true) => {
+                            let mut raw = vec![];
+                            text_escape(&mut raw, val);
+                            text.push_str(&String::from_utf8_lossy(&raw));
+                        }
+                        (None, true) => text.push_str(&record.datetime().to_rfc3339()),
+                        (value, false) => {
+                            let time = value.and_then(|val| chrono::DateTime::parse_from_rfc3339(val).ok()).unwrap_or_else(|| record.datetime().fixed_offset());
+                            let _ = write!(text, "{}", time.format_with_items(items.iter()));
+                        }
+                    }
+
+                    buf.extend_from_slice(text.as_bytes());
+                }
+                TemplatePart::Level(spec) => {
+                    match level_to_str(record.level()) {
+                        Some(val) => text.push_str(val),
+                        None => text.push_str(&record.level().to_string()),
+                    }
+
+                    if spec.write(buf, &text) {
+                        floor = buf.len();
+                    }
+                }
+                TemplatePart::Source(spec) => {
+                    match record.fields().find(|field| field.key() == "src") {
+                        Some(field) => {
+                            let mut val = vec![];
+                            text_value(&mut val, &field.value());
+                            text.push_str(&String::from_utf8_lossy(&val));
+                        }
+                        None => { let _ = write!(text, "{}:{}", record.source().file, record.source().line); }
+                    }
+
+                    if spec.write(buf, &text) {
+                        floor = buf.len();
+                    }
+                }
+                TemplatePart::Fields(spec) => {
+                    let mut pairs = vec![];
+
+                    for field in record.fields() {
+                        let key = field.key();
+
+                        if self.taken.iter().any(|val| *val == key) {
+                            continue;
+                        }
+
+                        field.value().flatten(&key, |key, val| {
+                            pairs.push(b' ');
+                            logfmt_key(&mut pairs, key);
+                            pairs.push(b'=');
+                            logfmt_value(&mut pairs, val);
+                        });
+                    }
+
+                    if spec.write(buf, String::from_utf8_lossy(pairs.get(1..).unwrap_or_default()).as_ref()) {
+                        floor = buf.len();
+                    }
+                }
+                TemplatePart::Field(key, spec) => {
+                    if let Some(field) = record.fields().find(|field| field.key() == key.as_str()) {
+                        let mut val = vec![];
+                        text_value(&mut val, &field.value());
+                        text.push_str(&String::from_utf8_lossy(&val));
+                    }
+
+                    if spec.write(buf, &text) {
+                        floor = buf.len();
+                    }
+                }
+            }
+        }
+
+        template_trim(buf, floor);
+
+        buf.push(b'\n');
+    }
+}
+
+impl TemplateSpec {
+    fn new(spec: &str) -> anyhow::Result<Self> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut this = Self {fill: ' ', align: '<', width: 0, limit: None};
+        let mut pos = 0;
+
+        match chars.as_slice() {
+            [fill, align, ..] if matches!(align, '<' | '>' | '^') => { this.fill = *fill; this.align = *align; pos = 2; }
+            [align, ..] if matches!(align, '<' | '>' | '^') => { this.align = *align; pos = 1; }
+            _ => {}
+        }
+
+        let rest: String = chars[pos..].iter().collect();
+        let (width, limit) = match rest.split_once('.') {
+            Some((width, limit)) => (width, Some(limit)),
+            None => (rest.as_str(), None),
+        };
+
+        if !width.is_empty() {
+            this.width = width.parse().map_err(|_| anyhow::anyhow!("invalid width in template spec '{}'", spec))?;
+        }
+
+        if let Some(limit) = limit {
+            this.limit = Some(limit.parse().map_err(|_| anyhow::anyhow!("invalid precision in template spec '{}'", spec))?);
+        }
+
+        Ok(this)
+    }
+
+    // returns true if padding is written after the text
+    fn write(&self, buf: &mut Vec<u8>, text: &str) -> bool {
+        let text = match self.limit.and_then(|limit| text.char_indices().nth(limit)) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+
+        let pad = self.width.saturating_sub(text.chars().count());
+        let (left, right) = match self.align {
+            '>' => (pad, 0),
+            '^' => (pad / 2, pad - pad / 2),
+            _ => (0, pad),
+        };
+
+        let mut fill = [0; 4];
+        let fill = self.fill.encode_utf8(&mut fill).as_bytes();
+
+        (0..left).for_each(|_| buf.extend_from_slice(fill));
+        buf.extend_from_slice(text.as_bytes());
+        (0..right).for_each(|_| buf.extend_from_slice(fill));
+
+        right > 0
+    }
+}
+
+#[inline]
+fn template_trim(buf: &mut Vec<u8>, floor: usize) {
+    while buf.len() > floor && buf.last() == Some(&b' ') {
+        buf.pop();
+    }
+}
+
+/// Elastic Common Schema version
+pub const ECS_VERSION: &str = "8.11.0";
+
+/// Output JSON in the Elastic Common Schema
+///
+/// Fields produced by the built-in plugins are renamed: `time` to `@timestamp`, `level` to
+/// `log.level`, `msg` to `message`, and `src` to `log.origin.file.name` and `log.origin.file.line`,
+/// plus `log.origin.function` if `src` starts with the module or function of the record's source.
+/// The `ecs.version` is added, and keys joined by dots are nested into real JSON objects.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("level", &"info");
+/// record.append("time", &"2024-01-03T11:01:00.123+08:00");
+/// record.append("msg", &"hello");
+/// record.append("http.request.method", &"GET");
+/// record.append("src", &"examples/hello_world.rs:9");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&logkit::EcsFormat)),
+///     concat!(
+///         r#"{"@timestamp":"2024-01-03T11:01:00.123+08:00","log":{"level":"info","origin":{"file":{"name":"examples/hello_world.rs","line":9}}},"#,
+///         r#""message":"hello","ecs":{"version":"8.11.0"},"http":{"request":{"method":"GET"}}}"#,
+///         "\n",
+///     )
+/// );
+///
+/// let source = logkit::Source::new("src/my app/main.rs", 5, 41).with_function("main");
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, source);
+/// record.append("time", &"2024-01-03T11:01:00.123+08:00");
+/// record.append("src", &"main src/my app/main.rs:5:41");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&logkit::EcsFormat)),
+///     concat!(
+///         r#"{"@timestamp":"2024-01-03T11:01:00.123+08:00","log":{"level":"info","origin":{"file":{"name":"src/my app/main.rs","line":5},"#,
+///         r#""function":"main"}},"ecs":{"version":"8.11.0"}}"#,
+///         "\n",
+///     )
+/// );
+/// ```
+pub struct EcsFormat;
+
+impl Format for EcsFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let mut root = Value::Object(vec![]);
+        let mut rest = vec![];
+        let mut src = None;
+
+        let time = match record.fields().find(|field| field.key() == "time") {
+            Some(field) => field.value().into_owned(),
+            None => Value::Str(record.datetime().to_rfc3339_opts(chrono::SecondsFormat::Millis, false).into()),
+        };
+
+        root.insert_nested("@timestamp", time);
+        root.insert_nested("log.level", match level_to_str(record.level()) {
+            Some(val) => Value::Str(val.into()),
+            None => Value::Str(record.level().to_string().into()),
+        });
+
+        for field in record.fields() {
+            match field.key().as_ref() {
+                "time" | "level" => {}
+                "msg" => root.insert_nested("message", field.value().into_owned()),
+                "src" => src = Some(field.value().into_owned()),
+                _ => rest.push(field),
+            }
+        }
+
+        match src {
+            Some(Value::Str(src)) => {
+                let (function, file, line) = src_parts(&src, record.source());
+                root.insert_nested("log.origin.file.name", Value::Str(file.to_string().into()));
+
+                if let Some(line) = line {
+                    root.insert_nested("log.origin.file.line", line.parse().map_or(Value::Str(line.to_string().into()), Value::Uint));
+                }
+
+                if let Some(function) = function {
+                    root.insert_nested("log.origin.function", Value::Str(function.to_string().into()));
+                }
+            }
+            Some(Value::Object(src)) => {
+                for (key, val) in src {
+                    match key.as_ref() {
+                        "file" => root.insert_nested("log.origin.file.name", val),
+                        "line" => root.insert_nested("log.origin.file.line", val),
+                        "function" => root.insert_nested("log.origin.function", val),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        root.insert_nested("ecs.version", Value::Str(ECS_VERSION.into()));
+
+        for field in rest {
+            root.insert_nested(&field.key(), field.value().into_owned());
+        }
+
+        root.encode(buf);
+        buf.push(b'\n');
+    }
+}
+
+// split the string form of SourcePlugin, `[module::function ]file:line[:column]`, the prefix is
+// split off only if it's the module or function of the source, paths may contain spaces
+fn src_parts<'a>(src: &'a str, source: &Source) -> (Option<&'a str>, &'a str, Option<&'a str>) {
+    let (module, function) = (source.module(), source.function());
+    let joined = src.strip_prefix(module).and_then(|rest| rest.strip_prefix("::")).and_then(|rest| rest.strip_prefix(function));
+    let location = [joined, src.strip_prefix(module), src.strip_prefix(function)].into_iter().flatten().filter(|rest| rest.len() < src.len()).find_map(|rest| rest.strip_prefix(' '));
+
+    let (function, location) = match location {
+        Some(location) => (Some(&src[..src.len() - location.len() - 1]), location),
+        None => (None, src),
+    };
+
+    let number = |text: &str| !text.is_empty() && text.bytes().all(|ch| ch.is_ascii_digit());
+
+    match location.rsplit_once(':') {
+        Some((rest, last)) if number(last) => match rest.rsplit_once(':') {
+            Some((file, line)) if number(line) => (function, file, Some(line)),
+            _ => (function, rest, Some(last)),
+        },
+        _ => (function, location, None),
+    }
+}
+
+/// Output JSON for Google Cloud Logging
+///
+/// The agents on GKE and Cloud Run parse these special keys from stdout:
+///
+/// - `severity` mapped from the level, custom levels can be mapped with `with_severity`
+/// - `message` renamed from `msg`
+/// - `logging.googleapis.com/sourceLocation` split from `src`, or taken from a nested `src`
+/// - `logging.googleapis.com/trace` renamed from `trace`, prefixed with the project if set
+/// - `logging.googleapis.com/spanId` renamed from `span_id`
+/// - `logging.googleapis.com/trace_sampled` renamed from `trace_sampled`
+/// - `httpRequest` renamed from `http_request`
+///
+/// ```
+/// pub const LEVEL_NOTICE : logkit::Level = 10;
+///
+/// let format = logkit::GcpFormat::default().with_project("my-project").with_severity(LEVEL_NOTICE, "NOTICE");
+///
+/// let mut record = logkit::Record::new(LEVEL_NOTICE, logkit::source!());
+/// record.append("msg", &"hello");
+/// record.append("trace", &"4bf92f3577b34da6");
+/// record.append("src", &"src/main.rs:9");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     concat!(
+///         r#"{"severity":"NOTICE","message":"hello","logging.googleapis.com/trace":"projects/my-project/traces/4bf92f3577b34da6","#,
+///         r#""logging.googleapis.com/sourceLocation":{"file":"src/main.rs","line":"9"}}"#,
+///         "\n",
+///     )
+/// );
+///
+/// let source = logkit::Source::new("src/main.rs", 5, 41).with_function("main");
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, source);
+/// record.append("src", &"main src/main.rs:5:41");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     r#"{"severity":"INFO","logging.googleapis.com/sourceLocation":{"file":"src/main.rs","line":"5","function":"main"}}"#.to_string() + "\n",
+/// );///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("src", &"src/my app.rs:9");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     r#"{"severity":"INFO","logging.googleapis.com/sourceLocation":{"file":"src/my app.rs","line":"9"}}"#.to_string() + "\n",
+/// );
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct GcpFormat {
+    /// project id used to complete trace ids
+    pub project: Option<String>,
+
+    /// severities of custom levels
+    pub severities: Vec<(Level, &'static str)>,
+}
+
+impl GcpFormat {
+    /// Complete trace ids with a project id
+    pub fn with_project(mut self, project: impl Into<String>) -> Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    /// Map a level to a severity, like NOTICE, CRITICAL, ALERT or EMERGENCY
+    pub fn with_severity(mut self, level: Level, severity: &'static str) -> Self {
+        self.severities.retain(|(val, _)| *val != level);
+        self.severities.push((level, severity));
+        self
+    }
+
+    /// Severity of a level
+    ///
+    /// ```
+    /// assert_eq!(logkit::GcpFormat::default().severity(logkit::LEVEL_WARN), "WARNING");
+    /// assert_eq!(logkit::GcpFormat::default().severity(10), "DEFAULT");
+    /// ```
+    pub fn severity(&self, level: Level) -> &'static str {
+        if let Some((_, severity)) = self.severities.iter().find(|(val, _)| *val == level) {
+            return severity;
+        }
+
+        match level {
+            LEVEL_TRACE | LEVEL_DEBUG => "DEBUG",
+            LEVEL_INFO => "INFO",
+            LEVEL_WARN => "WARNING",
+            LEVEL_ERROR => "ERROR",
+            _ => "DEFAULT",
+        }
+    }
+}
+
+impl Format for GcpFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let mut items = vec![("severity".into(), Value::Str(self.severity(record.level()).into()))];
+
+        for field in record.fields() {
+            let key = field.key();
+
+            match key.as_ref() {
+                "level" => {}
+                "msg" => items.push(("message".into(), field.value().into_owned())),
+                "trace" => {
+                    let trace = match (field.value().into_owned(), &self.project) {
+                        (Value::Str(val), Some(project)) if !val.starts_with("projects/") => Value::Str(format!("projects/{}/traces/{}", project, val).into()),
+                        (val, _) => val,
+                    };
+
+                    items.push(("logging.googleapis.com/trace".into(), trace));
+                }
+                "span_id" => items.push(("logging.googleapis.com/spanId".into(), field.value().into_owned())),
+                "trace_sampled" => items.push(("logging.googleapis.com/trace_sampled".into(), field.value().into_owned())),
+                "http_request" => items.push(("httpRequest".into(), field.value().into_owned())),
+                "src" => {
+                    let mut location = vec![];
+
+                    match field.value().into_owned() {
+                        Value::Str(src) => {
+                            let (function, file, line) = src_parts(&src, record.source());
+                            location.push(("file".into(), Value::Str(file.to_string().into())));
+
+                            if let Some(line) = line {
+                                location.push(("line".into(), Value::Str(line.to_string().into())));
+                            }
+
+                            if let Some(function) = function {
+                                location.push(("function".into(), Value::Str(function.to_string().into())));
+                            }
+                        }
+                        Value::Object(src) => {
+                            for (key, val) in src {
+                                match (key.as_ref(), val) {
+                                    ("file" | "function", val) => location.push((key, val)),
+                                    ("line", Value::Uint(line)) => location.push(("line".into(), Value::Str(line.to_string().into()))),
+                                    _ => {}
+                                }
+                            }
+                        }
+                        other => location.push(("file".into(), other)),
+                    }
+
+                    items.push(("logging.googleapis.com/sourceLocation".into(), Value::Object(location)));
+                }
+                _ => items.push((key, field.value().into_owned())),
+            }
+        }
+
+        Value::Object(items).encode(buf);
+        buf.push(b'\n');
+    }
+}
+
+/// Output JSON in the CloudWatch Embedded Metric Format
+///
+/// Records carrying fields marked by `Record::metric` get an `_aws` metadata block, which lets
+/// CloudWatch extract the metrics. Dimensions marked by `Record::dimension` are added to the ones
+/// of the format. Other records are output unchanged.
+///
+/// ```
+/// let format = logkit::EmfFormat::new("MyApp").with_dimension("service");
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("time", &"2024-01-03T11:01:00.123+08:00");
+/// record.append("service", &"checkout");
+/// record.append("region", &"us-east-1").dimension("region");
+/// record.metric("latency", &12.5, "Milliseconds");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     concat!(
+///         r#"{"time":"2024-01-03T11:01:00.123+08:00","service":"checkout","region":"us-east-1","latency":12.5,"#,
+///         r#""_aws":{"Timestamp":1704250860123,"CloudWatchMetrics":[{"Namespace":"MyApp","Dimensions":[["service","region"]],"#,
+///         r#""Metrics":[{"Name":"latency","Unit":"Milliseconds"}]}]}}"#,
+///         "\n",
+///     )
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmfFormat {
+    /// metric namespace
+    pub namespace: String,
+
+    /// dimensions of all metrics
+    pub dimensions: Vec<String>,
+}
+
+impl EmfFormat {
+    /// Create an EmfFormat with a namespace
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {namespace: namespace.into(), dimensions: vec![]}
+    }
+
+    /// Add a dimension to all metrics
+    pub fn with_dimension(mut self, key: impl Into<String>) -> Self {
+        self.dimensions.push(key.into());
+        self
+    }
+}
+
+impl Format for EmfFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let json = record.buffer();
+
+        if record.metrics().is_empty() || !json.ends_with(b"}\n") {
+            buf.extend_from_slice(json);
+            return;
+        }
+
+        let time = record.fields()
+            .find(|field| field.key() == "time")
+            .and_then(|field| field.value().as_str().and_then(|val| chrono::DateTime::parse_from_rfc3339(val).ok()))
+            .map_or_else(|| record.datetime().timestamp_millis(), |val| val.timestamp_millis());
+
+        let dimensions: Vec<&str> = self.dimensions.iter().chain(record.dimensions()).map(|val| val.as_str()).collect();
+        let metrics: Vec<Value> = record.metrics().iter().map(|metric| Value::Object(vec![
+            ("Name".into(), Value::Str(metric.key.as_str().into())),
+            ("Unit".into(), Value::Str(metric.unit.into())),
+        ])).collect();
+
+        let directive = Value::Object(vec![
+            ("Namespace".into(), Value::Str(self.namespace.as_str().into())),
+            ("Dimensions".into(), Value::Array(vec![Value::Array(dimensions.into_iter().map(|val| Value::Str(val.into())).collect())])),
+            ("Metrics".into(), Value::Array(metrics)),
+        ]);
+
+        let aws = Value::Object(vec![
+            ("Timestamp".into(), Value::Int(time)),
+            ("CloudWatchMetrics".into(), Value::Array(vec![directive])),
+        ]);
+
+        buf.extend_from_slice(&json[..json.len() - 2]);
+
+        if json.len() > 3 {
+            buf.push(b',');
+        }
+
+        buf.extend_from_slice(b"\"_aws\":");
+        aws.encode(buf);
+        buf.extend_from_slice(b"}\n");
+    }
+}
+
+/// Output CSV or TSV
+///
+/// Each record is a row of the fixed columns, followed by a last column holding the other fields
+/// as a JSON object. Strings are written as is and other values as JSON. Cells are quoted per
+/// RFC 4180 when necessary, and rows end with CRLF. Targets write the header row at the beginning
+/// of new files.
+///
+/// Strings starting with `=`, `+`, `-`, `@`, a tab or a carriage return are prefixed with a single
+/// quote, so spreadsheets don't evaluate them as formulas. Use `allow_formulas` to write them as is.
+///
+/// ```
+/// let format = logkit::CsvFormat::new(["time", "level", "msg"]);
+///
+/// let mut header = vec![];
+/// logkit::Format::header(&format, &mut header);
+/// assert_eq!(header, b"time,level,msg,fields\r\n");
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("level", &"info");
+/// record.append("msg", &"paid \"$12,000\"");
+/// record.append("account", &1001);
+/// record.append("memo", &"Q1");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     ",info,\"paid \"\"$12,000\"\"\",\"{\"\"account\"\":1001,\"\"memo\"\":\"\"Q1\"\"}\"\r\n",
+/// );
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("level", &-1);
+/// record.append("msg", &"=HYPERLINK(\"http://x\")");
+/// record.finish();
+///
+/// assert_eq!(String::from_utf8_lossy(record.render(&format)), ",-1,\"'=HYPERLINK(\"\"http://x\"\")\",\r\n");
+/// assert_eq!(String::from_utf8_lossy(record.render(&format.allow_formulas())), ",-1,\"=HYPERLINK(\"\"http://x\"\")\",\r\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct CsvFormat {
+    /// keys of the fixed columns
+    pub columns: Vec<String>,
+
+    /// cell delimiter
+    pub delimiter: u8,
+
+    /// write strings that look like formulas as is
+    pub formulas: bool,
+}
+
+impl CsvFormat {
+    /// Create a comma-separated format with columns
+    pub fn new(columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {columns: columns.into_iter().map(Into::into).collect(), delimiter: b',', formulas: false}
+    }
+
+    /// Create a tab-separated format with columns
+    pub fn tsv(columns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {delimiter: b'\t', ..Self::new(columns)}
+    }
+
+    /// Write strings that look like formulas as is, without the single quote prefix
+    pub fn allow_formulas(self) -> Self {
+        Self {formulas: true, ..self}
+    }
+}
+
+impl Format for CsvFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let fields: Vec<Field> = record.fields().collect();
+        let mut used = vec![false; fields.len()];
+
+        for column in &self.columns {
+            let found = fields.iter().enumerate().find(|(i, field)| !used[*i] && field.key() == column.as_str());
+
+            if let Some((i, field)) = found {
+                used[i] = true;
+
+                match field.value().as_ref() {
+                    Value::Null => {}
+                    Value::Str(val) if !self.formulas && val.starts_with(['=', '+', '-', '@', '\t', '\r']) => {
+                        csv_cell(buf, self.delimiter, format!("'{}", val).as_bytes());
+                    }
+                    Value::Str(val) => csv_cell(buf, self.delimiter, val.as_bytes()),
+                    _ => csv_cell(buf, self.delimiter, &field.json()),
+                }
+            }
+
+            buf.push(self.delimiter);
+        }
+
+        let mut extra = vec![];
+
+        for (field, _) in fields.iter().zip(&used).filter(|(_, used)| !**used) {
+            extra.push(if extra.is_empty() { b'{' } else { b',' });
+            field.key().as_ref().encode(&mut extra);
+            extra.push(b':');
+            extra.extend_from_slice(&field.json());
+        }
+
+        if !extra.is_empty() {
+            extra.push(b'}');
+            csv_cell(buf, self.delimiter, &extra);
+        }
+
+        buf.extend_from_slice(b"\r\n");
+    }
+
+    fn header(&self, buf: &mut Vec<u8>) {
+        for column in &self.columns {
+            csv_cell(buf, self.delimiter, column.as_bytes());
+            buf.push(self.delimiter);
+        }
+
+        buf.extend_from_slice(b"fields\r\n");
+    }
+}
+
+fn csv_cell(buf: &mut Vec<u8>, delimiter: u8, text: &[u8]) {
+    if !text.iter().any(|ch| matches!(*ch, b'"' | b'\r' | b'\n') || *ch == delimiter) {
+        buf.extend_from_slice(text);
+        return;
+    }
+
+    buf.push(b'"');
+
+    for ch in text {
+        if *ch == b'"' {
+            buf.push(b'"');
+        }
+
+        buf.push(*ch);
+    }
+
+    buf.push(b'"');
+}
+
+/// Output ArcSight Common Event Format
+///
+/// Records are rendered as `CEF:0|Vendor|Product|Version|Signature ID|Name|Severity|Extension`:
+///
+/// - `Signature ID` from the `signature` field, or the level's name
+/// - `Name` from the `msg` field, or the signature id
+/// - `Severity` mapped from the level, custom levels can be mapped with `with_severity`
+/// - `Extension` from the other fields, nested fields are flattened into keys joined by dots
+///
+/// Keys are renamed to standard CEF keys by the key table, like `host` to `dvchost`. The `time`
+/// field becomes `rt` in milliseconds, and a field mapped to a custom string like `cs1` comes with
+/// a label holding its original key.
+///
+/// ```
+/// let format = logkit::CefFormat::new("Acme", "Gateway", "1.0").with_key("client_ip", "src");
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_WARN, logkit::source!());
+/// record.append("time", &"2024-01-03T11:01:00+08:00");
+/// record.append("msg", &"login failed");
+/// record.append("signature", &"auth|001");
+/// record.append("client_ip", &"10.0.0.1");
+/// record.append("user", &"bob");
+/// record.append("src", &"src/main.rs:9");
+/// record.append("note", &"a=b\\c");
+/// record.finish();
+///
+/// assert_eq!(
+///     String::from_utf8_lossy(record.render(&format)),
+///     concat!(
+///         r"CEF:0|Acme|Gateway|1.0|auth\|001|login failed|6|",
+///         r"rt=1704250860000 src=10.0.0.1 suser=bob cs1=src/main.rs:9 cs1Label=src note=a\=b\\c",
+///         "\n",
+///     )
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct CefFormat {
+    /// device vendor
+    pub vendor: String,
+
+    /// device product
+    pub product: String,
+
+    /// device version
+    pub version: String,
+
+    /// severities of custom levels
+    pub severities: Vec<(Level, u8)>,
+
+    /// field keys and their CEF keys
+    pub keys: Vec<(String, String)>,
+}
+
+impl CefFormat {
+    /// Create a CefFormat with the device info and the default key table
+    pub fn new(vendor: impl Into<String>, product: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            vendor: vendor.into(),
+            product: product.into(),
+            version: version.into(),
+            severities: vec![],
+            keys: [("time", "rt"), ("host", "dvchost"), ("pid", "dvcpid"), ("user", "suser"), ("src", "cs1")]
+                .into_iter()
+                .map(|(key, cef)| (key.to_string(), cef.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Map a level to a severity between 0 and 10
+    pub fn with_severity(mut self, level: Level, severity: u8) -> Self {
+        self.severities.retain(|(val, _)| *val != level);
+        self.severities.push((level, severity.min(10)));
+        self
+    }
+
+    /// Map a field key to a CEF key
+    pub fn with_key(mut self, key: impl Into<String>, cef: impl Into<String>) -> Self {
+        let key = key.into();
+        self.keys.retain(|(val, _)| *val != key);
+        self.keys.push((key, cef.into()));
+        self
+    }
+
+    /// Severity of a level
+    ///
+    /// ```
+    /// let format = logkit::CefFormat::new("Acme", "Gateway", "1.0");
+    /// assert_eq!(format.severity(logkit::LEVEL_ERROR), 8);
+    /// assert_eq!(format.severity(10), 5);
+    /// ```
+    pub fn severity(&self, level: Level) -> u8 {
+        if let Some((_, severity)) = self.severities.iter().find(|(val, _)| *val == level) {
+            return *severity;
+        }
+
+        match level {
+            LEVEL_TRACE => 0,
+            LEVEL_DEBUG => 1,
+            LEVEL_INFO => 3,
+            LEVEL_WARN => 6,
+            LEVEL_ERROR => 8,
+            _ => 5,
+        }
+    }
+}
+
+impl Format for CefFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let mut signature = None;
+        let mut name = None;
+        let mut extension = vec![];
+
+        for field in record.fields() {
+            let key = field.key();
+
+            match key.as_ref() {
+                "level" => {}
+                "signature" if signature.is_none() => signature = Some(field.value()),
+                "msg" if name.is_none() => name = Some(field.value()),
+                _ => field.value().flatten(&key, |key, val| {
+                    let cef = self.keys.iter().find(|(item, _)| item == key).map(|(_, cef)| cef.as_str());
+
+                    match (cef, val) {
+                        (_, Value::Null) => return,
+                        (Some("rt"), Value::Str(time)) => match chrono::DateTime::parse_from_rfc3339(time) {
+                            Ok(time) => cef_pair(&mut extension, "rt", &Value::Int(time.timestamp_millis())),
+                            Err(_) => cef_pair(&mut extension, "rt", val),
+                        },
+                        (Some(cef), _) => cef_pair(&mut extension, cef, val),
+                        (None, _) => cef_pair(&mut extension, key, val),
+                    }
+
+                    if let Some(cef) = cef.filter(|cef| cef.starts_with("cs") && cef[2..].parse::<u8>().is_ok()) {
+                        cef_pair(&mut extension, &format!("{}Label", cef), &Value::Str(key.into()));
+                    }
+                }),
+            }
+        }
+
+        let signature = match signature {
+            Some(val) => val,
+            None => Cow::Owned(Value::Str(level_to_str(record.level()).map_or_else(|| record.level().to_string().into(), Into::into))),
+        };
+
+        buf.extend_from_slice(b"CEF:0|");
+
+        for text in [self.vendor.as_str(), self.product.as_str(), self.version.as_str()] {
+            cef_header(buf, &Value::Str(text.into()));
+            buf.push(b'|');
+        }
+
+        cef_header(buf, &signature);
+        buf.push(b'|');
+        cef_header(buf, name.as_ref().unwrap_or(&signature));
+        buf.push(b'|');
+        buf.extend_from_slice(self.severity(record.level()).to_string().as_bytes());
+        buf.push(b'|');
+
+        if let Some(b' ') = extension.last() {
+            extension.pop();
+        }
+
+        buf.extend_from_slice(&extension);
+        buf.push(b'\n');
+    }
+}
+
+fn cef_header(buf: &mut Vec<u8>, value: &Value) {
+    let mut text = vec![];
+
+    match value {
+        Value::Str(val) => text.extend_from_slice(val.as_bytes()),
+        _ => value.encode(&mut text),
+    }
+
+    for ch in text {
+        match ch {
+            b'\\' | b'|' => { buf.push(b'\\'); buf.push(ch); }
+            b'\r' | b'\n' => buf.push(b' '),
+            _ => buf.push(ch),
+        }
+    }
+}
+
+fn cef_pair(buf: &mut Vec<u8>, key: &str, value: &Value) {
+    for ch in key.chars() {
+        match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' => buf.push(ch as u8),
+            _ => buf.push(b'_'),
+        }
+    }
+
+    buf.push(b'=');
+
+    let mut text = vec![];
+
+    match value {
+        Value::Str(val) => text.extend_from_slice(val.as_bytes()),
+        Value::Array(_) => text.extend_from_slice(b"[]"),
+        Value::Object(_) => text.extend_from_slice(b"{}"),
+        _ => value.encode(&mut text),
+    }
+
+    for ch in text {
+        match ch {
+            b'\\' | b'=' => { buf.push(b'\\'); buf.push(ch); }
+            b'\n' => buf.extend_from_slice(b"\\n"),
+            b'\r' => buf.extend_from_slice(b"\\r"),
+            _ => buf.push(ch),
+        }
+    }
+
+    buf.push(b' ');
+}
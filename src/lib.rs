@@ -7,7 +7,8 @@
 //! ## Basic Syntax
 //!
 //! Five convenient macros are available for use: `trace`, `debug`, `info`, `warn`, and `error`.
-//! These support the following log formats, and you can define custom macros if necessary.
+//! These support the following log formats, and you can define custom macros if necessary. They
+//! use the default logger unless another one is given with `logger:`.
 //!
 //! ```
 //! #[macro_use] extern crate logkit;
@@ -18,6 +19,9 @@
 //! trace!(name = "Alice", age = 20); // outputs only fields, no message
 //! trace!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 //! trace!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+//!
+//! let req_log = logkit::default_logger().child(fields!(request_id = 42));
+//! trace!(logger: req_log, "the same syntax with another logger, like a child logger");
 //! ```
 //!
 //! ## Default Logger
@@ -159,29 +163,61 @@
 //! info!("record will be output to both stderr and stdout now");
 //! ```
 //!
+//! ## Output Format
+//!
+//! Targets write records in JSON by default. A different format can be chosen for each target,
+//! formats read the structured fields of a record and render them into bytes.
+//!
+//! ```
+//! #[macro_use] extern crate logkit;
+//!
+//! use logkit::Target;
+//!
+//! let mut logger = logkit::Logger::new(Some(&logkit::StderrTarget));
+//! logger.route(logkit::StdoutTarget.with_format(logkit::JsonFormat));
+//! logkit::set_default_logger(logger);
+//!
+//! info!("record will be rendered by each target's format");
+//! ```
+//!
 //! **Happy Logging!**
 #![warn(missing_docs)]
 #![deny(clippy::unwrap_used, clippy::expect_used, clippy::let_underscore_future)]
 
+pub mod cbor;
 pub mod define;
+pub mod format;
 pub mod logger;
 pub mod macros;
+pub mod msgpack;
 pub mod plugin;
+pub mod protobuf;
 pub mod record;
 pub mod source;
 pub mod target;
+pub mod value;
 
+#[doc(hidden)]
+pub use cbor::*;
 #[doc(inline)]
 pub use define::*;
 #[doc(hidden)]
+pub use format::*;
+#[doc(hidden)]
 pub use logger::*;
 #[doc(inline)]
 pub use macros::*;
 #[doc(hidden)]
+pub use msgpack::*;
+#[doc(hidden)]
 pub use plugin::*;
 #[doc(hidden)]
+pub use protobuf::*;
+#[doc(hidden)]
 pub use record::*;
 #[doc(hidden)]
 pub use source::*;
 #[doc(hidden)]
-pub use target::*;
\ No newline at end of file
+pub use target::*;
+#[doc(hidden)]
+pub use value::*;
\ No newline at end of file
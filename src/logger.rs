@@ -4,6 +4,9 @@ use super::record::*;
 use super::source::*;
 use super::plugin::*;
 use super::target::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static GENERATION: AtomicU64 = AtomicU64::new(1); // generation 0 means no directives
 
 /// The Logger
 ///
@@ -11,6 +14,11 @@ use super::target::*;
 /// and all other logging functionalities.
 pub struct Logger {
     barrier: Level,                       // log level filter
+    floor: Level,                         // lowest level of barrier and directives
+    generation: u64,                      // key of the directives in callsite caches, 48 bits
+    directives: Vec<(String, Level)>,     // module or file prefixes, longest first
+    duplicate: DuplicatePolicy,           // duplicate keys resolution
+    fields: Fields,                       // static fields of all records
     records: Mutex<Vec<Record>>,          // records pool
     plugins: Vec<Box<dyn Plugin>>,        // middlewares
     targets: Vec<Box<dyn Target>>,        // output targets
@@ -29,6 +37,11 @@ impl Logger {
     pub const fn new(default: Option<&'static dyn Target>) -> Self {
         Self {
             barrier: LEVEL_TRACE,
+            floor: LEVEL_TRACE,
+            generation: 0,
+            directives: vec![],
+            duplicate: DuplicatePolicy::KeepAll,
+            fields: Fields::new(),
             records: Mutex::new(vec![]),
             plugins: vec![],
             targets: vec![],
@@ -65,9 +78,110 @@ impl Logger {
     /// ```
     pub fn limit(&mut self, level: Level) -> &mut Self {
         self.barrier = level;
+        self.refresh();
         self
     }
 
+    /// Set log levels per module or file with `RUST_LOG`-style directives
+    ///
+    /// Directives are separated by commas. `prefix=level` applies to the modules under the
+    /// prefix, like `my_crate::db`, or to the files under it, like `src/db`. A bare
+    /// level sets the limit of other logs, and a bare prefix enables all levels for it. The
+    /// longest matching prefix wins, the level `off` disables logs entirely. Levels are case
+    /// insensitive. Previous directives are replaced.
+    ///
+    /// The matching directive is cached in each call site, so a disabled log costs about the same
+    /// as the `allow` check. Loggers sharing a call site keep their own directives.
+    ///
+    /// ```
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.directives("my_crate::db=trace,my_crate::db::pool=off,hyper=warn,info").unwrap();
+    /// assert_eq!(logger.level(), logkit::LEVEL_INFO);
+    ///
+    /// let source = |module| logkit::Source::default().with_module(module);
+    /// assert!(logger.allow_source(logkit::LEVEL_TRACE, &source("my_crate::db")));
+    /// assert!(logger.allow_source(logkit::LEVEL_TRACE, &source("my_crate::db::query")));
+    /// assert!(!logger.allow_source(logkit::LEVEL_ERROR, &source("my_crate::db::pool")));
+    /// assert!(!logger.allow_source(logkit::LEVEL_TRACE, &source("my_crate::dbx")));
+    /// assert!(!logger.allow_source(logkit::LEVEL_INFO, &source("hyper::client")));
+    /// assert!(logger.allow_source(logkit::LEVEL_INFO, &source("my_crate")));
+    ///
+    /// assert!(logger.spawn(logkit::LEVEL_DEBUG, logkit::source!()).is_none());
+    /// logger.directives(&format!("{}=debug", module_path!())).unwrap();
+    /// assert!(logger.spawn(logkit::LEVEL_DEBUG, logkit::source!()).is_some());
+    ///
+    /// assert!(logger.directives("hyper=loud").is_err());
+    ///
+    /// logger.directives("my_crate=DEBUG,Warn").unwrap();
+    /// assert_eq!(logger.level(), logkit::LEVEL_WARN);
+    /// assert!(logger.allow_source(logkit::LEVEL_DEBUG, &source("my_crate")));
+    ///
+    /// logger.directives("src/db=trace,info").unwrap();
+    /// assert!(logger.allow_source(logkit::LEVEL_TRACE, &logkit::Source::new("src/db/pool.rs", 1, 1)));
+    /// assert!(!logger.allow_source(logkit::LEVEL_TRACE, &logkit::Source::new("src/dbx.rs", 1, 1)));
+    ///
+    /// let mut quiet = logkit::Logger::new(None);
+    /// quiet.directives("error").unwrap();
+    /// let mut loud = logkit::Logger::new(None);
+    /// loud.directives(&format!("{}=trace,error", module_path!())).unwrap();
+    ///
+    /// for _ in 0..2 {
+    ///     let source = logkit::source!(); // the same call site for both loggers
+    ///     assert!(!quiet.allow_source(logkit::LEVEL_INFO, &source));
+    ///     assert!(loud.allow_source(logkit::LEVEL_INFO, &source));
+    /// }
+    /// ```
+    pub fn directives(&mut self, spec: &str) -> anyhow::Result<&mut Self> {
+        let mut barrier = self.barrier;
+        let mut directives = vec![];
+
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (prefix, level) = match item.split_once('=') {
+                Some((prefix, level)) => (prefix.trim(), Self::parse_level(level.trim())?),
+                None => match Self::parse_level(item) {
+                    Ok(level) => ("", level),
+                    Err(_) => (item, LEVEL_TRACE),
+                },
+            };
+
+            match prefix.is_empty() {
+                true => barrier = level,
+                false => directives.push((prefix.to_string(), level)),
+            }
+        }
+
+        directives.sort_by_key(|(prefix, _): &(String, Level)| std::cmp::Reverse(prefix.len()));
+
+        self.barrier = barrier;
+        self.directives = directives;
+        self.refresh();
+
+        Ok(self)
+    }
+
+    /// Get the level directives, longest prefix first
+    #[inline]
+    pub fn level_directives(&self) -> &[(String, Level)] {
+        &self.directives
+    }
+
+    fn parse_level(name: &str) -> anyhow::Result<Level> {
+        match (str_to_level(&name.to_ascii_lowercase()), name.eq_ignore_ascii_case("off")) {
+            (LEVEL_OFF, true) => Ok(LEVEL_OFF),
+            (LEVEL_OFF, false) => Err(anyhow::anyhow!("invalid log level: {}", name)),
+            (level, _) => Ok(level),
+        }
+    }
+
+    // recompute the floor and invalidate the levels cached in call sites
+    fn refresh(&mut self) {
+        self.floor = self.directives.iter().fold(self.barrier, |floor, (_, level)| floor.min(*level));
+        self.generation = match self.directives.is_empty() {
+            true => 0,
+            false => (GENERATION.fetch_add(1, Ordering::Relaxed) & 0xffff_ffff_ffff).max(1),
+        };
+    }
+
     /// Check if the log level is equal to or higher than the limit
     ///
     /// ```
@@ -86,6 +200,127 @@ impl Logger {
         level >= self.barrier
     }
 
+    /// Check if the log level is allowed for the source, according to the level directives
+    ///
+    /// Without directives, it's the same as `allow`.
+    #[inline]
+    pub fn allow_source(&self, level: Level, source: &Source) -> bool {
+        if level < self.floor {
+            return false;
+        }
+
+        self.generation == 0 || level >= self.resolve(source)
+    }
+
+    // level of the longest matching directive, cached in the call site
+    #[inline]
+    fn resolve(&self, source: &Source) -> Level {
+        let directive = match source.callsite().and_then(|callsite| callsite.get(self.generation)) {
+            Some(directive) => directive,
+            None => {
+                let directive = self.directives.iter().position(|(prefix, _)| {
+                    let matches = |path: &str, sep: &str| path.strip_prefix(prefix.as_str()).is_some_and(|rest| {
+                        rest.is_empty() || rest.starts_with(sep) || prefix.ends_with(sep)
+                    });
+                    matches(source.module(), "::") || matches(source.file, "/") || matches(source.file, "\\")
+                });
+
+                if let Some(callsite) = source.callsite() {
+                    callsite.set(self.generation, directive);
+                }
+
+                directive
+            }
+        };
+
+        directive.and_then(|index| self.directives.get(index)).map_or(self.barrier, |(_, level)| *level)
+    }
+
+    /// Set how to resolve fields with duplicate keys, all fields are kept by default
+    ///
+    /// Fields of plugins like `LevelPlugin` are never shadowed by fields of the caller, see
+    /// `Record::dedupe` for details.
+    ///
+    /// ```
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.mount(logkit::LevelPlugin);
+    /// logger.dedupe(logkit::DuplicatePolicy::LastWins);
+    ///
+    /// if let Some(mut record) = logger.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+    ///     record.append("level", &"fake").dimension("level");
+    ///     record.append("user_id", &1);
+    ///     record.append("user_id", &2);
+    ///     record.dedupe(logger.duplicate());
+    ///     record.finish();
+    ///     assert_eq!(record.dimensions(), ["level_2"]);
+    ///     assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"level\":\"info\",\"level_2\":\"fake\",\"user_id\":2}\n");
+    /// }
+    /// ```
+    pub fn dedupe(&mut self, policy: DuplicatePolicy) -> &mut Self {
+        self.duplicate = policy;
+        self
+    }
+
+    /// Get the policy of duplicate keys
+    #[inline]
+    pub fn duplicate(&self) -> DuplicatePolicy {
+        self.duplicate
+    }
+
+    /// Add fields to all records, encoded only once
+    ///
+    /// The fields are copied into each record as bytes, after the fields of the `pre` method of
+    /// plugins. Like the fields of plugins, they are never shadowed by the caller's fields.
+    ///
+    /// ```
+    /// #[macro_use] extern crate logkit;
+    ///
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.with_static_fields(fields!(service = "checkout", version = "1.2.0"));
+    ///
+    /// if let Some(mut record) = logger.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+    ///     record.finish();
+    ///     assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"service\":\"checkout\",\"version\":\"1.2.0\"}\n");
+    /// }
+    /// ```
+    pub fn with_static_fields(&mut self, fields: Fields) -> &mut Self {
+        self.fields.extend(&fields);
+        self
+    }
+
+    /// Get the static fields
+    #[inline]
+    pub fn static_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    /// Create a child logger with bound fields
+    ///
+    /// The child shares the plugins, targets and level of this logger, and adds the bound fields
+    /// to each record. Creating a child only encodes the bound fields.
+    ///
+    /// ```
+    /// #[macro_use] extern crate logkit;
+    ///
+    /// let req_log = logkit::default_logger().child(fields!(request_id = 42, user = "alice"));
+    /// info!(logger: req_log, "request received");
+    ///
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.with_static_fields(fields!(service = "checkout"));
+    ///
+    /// let child = logger.child(fields!(request_id = 42));
+    ///
+    /// if let Some(mut record) = child.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+    ///     record.append("msg", &"done");
+    ///     record.finish();
+    ///     assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"service\":\"checkout\",\"request_id\":42,\"msg\":\"done\"}\n");
+    /// }
+    /// ```
+    #[inline]
+    pub fn child(&self, fields: Fields) -> ChildLogger<'_> {
+        ChildLogger {parent: self, fields}
+    }
+
     /// Install a plugin for records
     ///
     /// A plugin acts as middleware for logs. For more details, refer to `plugin.rs`.
@@ -188,10 +423,15 @@ impl Logger {
     /// ```
     #[inline]
     pub fn spawn(&self, level: Level, source: Source) -> Option<Record> {
-        if !self.allow(level) {
+        if !self.allow_source(level, &source) {
             return None;
         }
 
+        self.create(level, source)
+    }
+
+    // kept out of line so that checking the level stays cheap at every call site
+    fn create(&self, level: Level, source: Source) -> Option<Record> {
         let record = match self.records.lock() {
             Ok(mut obj) => obj.pop(),
             Err(_) => return None
@@ -202,6 +442,8 @@ impl Logger {
             Some(val) => Record::set(val, level, source),
         };
 
+        record.by_plugin(true);
+
         for plugin in &self.plugins {
             if !plugin.pre(&mut record) {
                 self.reuse(record);
@@ -209,16 +451,21 @@ impl Logger {
             }
         }
 
+        record.extend(&self.fields);
+        record.by_plugin(false);
+
         Some(record)
     }
 
     /// Finish and output a record
     ///
     /// The `post` method of plugins will be called. If you wish to prevent output to targets,
-    /// simply return `false`. Once the `finish` method is invoked, the record will be directed
-    /// to all installed targets for output.
+    /// simply return `false`. Fields with duplicate keys are then resolved by the policy set with
+    /// `dedupe`. Once the `finish` method is invoked, the record will be directed to all installed
+    /// targets for output.
     ///
-    /// Note that the default target is always invoked first.
+    /// Note that the default target is always invoked first. Targets with a format receive the
+    /// record rendered by it, others receive the JSON buffer.
     ///
     /// ```
     /// let logger = logkit::Logger::new(Some(&logkit::StderrTarget));
@@ -230,6 +477,8 @@ impl Logger {
     /// ```
     #[inline]
     pub fn flush(&self, mut record: Record) {
+        record.by_plugin(true);
+
         for plugin in &self.plugins {
             if !plugin.post(&mut record) {
                 self.reuse(record);
@@ -237,19 +486,29 @@ impl Logger {
             }
         }
 
+        record.by_plugin(false);
+        record.dedupe(self.duplicate);
         record.finish();
 
         if let Some(target) = self.default {
-            target.write(record.buffer());
+            Self::output(target, &mut record);
         }
 
         for target in &self.targets {
-            target.write(record.buffer());
+            Self::output(target.as_ref(), &mut record);
         }
 
         self.reuse(record);
     }
 
+    #[inline]
+    fn output(target: &dyn Target, record: &mut Record) {
+        match target.format() {
+            None => target.write(record.buffer()),
+            Some(format) => target.write(record.render(format)),
+        }
+    }
+
     /// Places the record back into the object pool for reuse
     ///
     /// The `flush` method calls this function automatically, so typically you don't need to
@@ -260,4 +519,77 @@ impl Logger {
             obj.push(record)
         }
     }
-}
\ No newline at end of file
+}
+
+/// Child Logger
+///
+/// Created by `Logger::child`, it has the same `spawn` and `flush` methods as a logger, so it can
+/// be used with the level macros like `info!(logger: req_log, ...)` and the `record!` macro.
+pub struct ChildLogger<'a> {
+    parent: &'a Logger,
+    fields: Fields,
+}
+
+impl<'a> ChildLogger<'a> {
+    /// The logger sharing its plugins, targets and level
+    #[inline]
+    pub fn parent(&self) -> &'a Logger {
+        self.parent
+    }
+
+    /// The bound fields
+    #[inline]
+    pub fn fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    /// Create a child logger with more bound fields
+    ///
+    /// ```
+    /// #[macro_use] extern crate logkit;
+    ///
+    /// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+    /// let db_log = req_log.child(fields!(table = "users"));
+    /// assert_eq!(db_log.fields().len(), 2);
+    /// ```
+    #[inline]
+    pub fn child(&self, fields: Fields) -> ChildLogger<'a> {
+        let mut bound = self.fields.clone();
+        bound.extend(&fields);
+        ChildLogger {parent: self.parent, fields: bound}
+    }
+
+    /// Get current log level
+    #[inline]
+    pub fn level(&self) -> Level {
+        self.parent.level()
+    }
+
+    /// Check if the log level is equal to or higher than the limit
+    #[inline]
+    pub fn allow(&self, level: Level) -> bool {
+        self.parent.allow(level)
+    }
+
+    /// Check if the log level is allowed for the source
+    #[inline]
+    pub fn allow_source(&self, level: Level, source: &Source) -> bool {
+        self.parent.allow_source(level, source)
+    }
+
+    /// Create a new log record with the bound fields
+    #[inline]
+    pub fn spawn(&self, level: Level, source: Source) -> Option<Record> {
+        let mut record = self.parent.spawn(level, source)?;
+        record.by_plugin(true);
+        record.extend(&self.fields);
+        record.by_plugin(false);
+        Some(record)
+    }
+
+    /// Finish and output a record
+    #[inline]
+    pub fn flush(&self, record: Record) {
+        self.parent.flush(record);
+    }
+}
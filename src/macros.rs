@@ -50,10 +50,17 @@ pub fn set_default_logger(logger: Logger) {
 /// trace!(name = "Alice", age = 20); // outputs only fields, no message
 /// trace!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 /// trace!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+///
+/// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+/// trace!(logger: req_log, "use another logger, like a child logger");
 /// ```
 #[cfg(feature = "trace")]
 #[macro_export]
 macro_rules! trace {
+    (logger: $log:expr $(, $($arg:tt)*)?) => {{
+        $crate::record!($log, $crate::LEVEL_TRACE $(, $($arg)*)?)
+    }};
+
     ($($arg:tt)*) => {{
         $crate::record!($crate::default_logger(), $crate::LEVEL_TRACE, $($arg)*)
     }};
@@ -77,10 +84,17 @@ macro_rules! trace {
 /// debug!(name = "Alice", age = 20); // outputs only fields, no message
 /// debug!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 /// debug!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+///
+/// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+/// debug!(logger: req_log, "use another logger, like a child logger");
 /// ```
 #[cfg(feature = "debug")]
 #[macro_export]
 macro_rules! debug {
+    (logger: $log:expr $(, $($arg:tt)*)?) => {{
+        $crate::record!($log, $crate::LEVEL_DEBUG $(, $($arg)*)?)
+    }};
+
     ($($arg:tt)*) => {{
         $crate::record!($crate::default_logger(), $crate::LEVEL_DEBUG, $($arg)*)
     }};
@@ -104,10 +118,17 @@ macro_rules! debug {
 /// info!(name = "Alice", age = 20); // outputs only fields, no message
 /// info!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 /// info!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+///
+/// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+/// info!(logger: req_log, "use another logger, like a child logger");
 /// ```
 #[cfg(feature = "info")]
 #[macro_export]
 macro_rules! info {
+    (logger: $log:expr $(, $($arg:tt)*)?) => {{
+        $crate::record!($log, $crate::LEVEL_INFO $(, $($arg)*)?)
+    }};
+
     ($($arg:tt)*) => {{
         $crate::record!($crate::default_logger(), $crate::LEVEL_INFO, $($arg)*)
     }};
@@ -131,10 +152,17 @@ macro_rules! info {
 /// warn!(name = "Alice", age = 20); // outputs only fields, no message
 /// warn!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 /// warn!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+///
+/// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+/// warn!(logger: req_log, "use another logger, like a child logger");
 /// ```
 #[cfg(feature = "warn")]
 #[macro_export]
 macro_rules! warn {
+    (logger: $log:expr $(, $($arg:tt)*)?) => {{
+        $crate::record!($log, $crate::LEVEL_WARN $(, $($arg)*)?)
+    }};
+
     ($($arg:tt)*) => {{
         $crate::record!($crate::default_logger(), $crate::LEVEL_WARN, $($arg)*)
     }};
@@ -158,10 +186,17 @@ macro_rules! warn {
 /// error!(name = "Alice", age = 20); // outputs only fields, no message
 /// error!(name = "Alice", age = 20; "separate fields and messages with semicolon");
 /// error!(name = "Alice", age = 20; "println-like message {} {}! with fields", "Hello", "World");
+///
+/// let req_log = logkit::default_logger().child(fields!(request_id = 42));
+/// error!(logger: req_log, "use another logger, like a child logger");
 /// ```
 #[cfg(feature = "error")]
 #[macro_export]
 macro_rules! error {
+    (logger: $log:expr $(, $($arg:tt)*)?) => {{
+        $crate::record!($log, $crate::LEVEL_ERROR $(, $($arg)*)?)
+    }};
+
     ($($arg:tt)*) => {{
         $crate::record!($crate::default_logger(), $crate::LEVEL_ERROR, $($arg)*)
     }};
@@ -195,6 +230,11 @@ macro_rules! error {
 /// let mut name = "Alice";
 /// record!(logkit::default_logger(), logkit::LEVEL_TRACE, name = name, age = 10);
 /// record!(logkit::default_logger(), logkit::LEVEL_TRACE, name = name, age = 20); // field formatted twice
+///
+/// // dotted keys are expanded into nested objects: {"http":{"method":"GET","status":200}}
+/// record!(logkit::default_logger(), logkit::LEVEL_TRACE, http.method = "GET", http.status = 200);
+/// record!(logkit::default_logger(), logkit::LEVEL_TRACE, http.method = "GET", http.status = 200; "request done");
+/// record!(logkit::default_logger(), logkit::LEVEL_TRACE, http.method = "GET", id = 1, http.status = 200); // merged as well
 /// ```
 #[macro_export]
 macro_rules! record {
@@ -221,29 +261,53 @@ macro_rules! record {
         }
     }};
 
-    // record!(logkit::LEVEL_TRACE, name = "Alice", age = 20);
-    // {"name":"Alice","age":20}
-    ($log:expr, $lvl:expr, $($key:tt = $val:expr),+ $(,)?) => {{
+    // record!(logkit::LEVEL_TRACE, name = "Alice", age = 20, http.method = "GET");
+    // {"name":"Alice","age":20,"http":{"method":"GET"}}
+    ($log:expr, $lvl:expr, $($($key:tt).+ = $val:expr),+ $(,)?) => {{
         if let Some(mut record) = $log.spawn($lvl, $crate::source!()) {
-            $(record.append(stringify!($key), &$val);)+
+            $(record.append_nested(&[$(stringify!($key)),+], &$val);)+
             $log.flush(record);
         }
     }};
 
     // record!(logkit::LEVEL_TRACE, name = "Alice", age = 20; "I'm ready for adventure!");
     // {"msg":"I'm ready for adventure!","name":"Alice","age":20}
-    ($log:expr, $lvl:expr, $($key:tt = $val:expr),+; $fmt:literal) => {{
-        $crate::record!($log, $lvl, $($key = $val),+; $fmt, )
+    ($log:expr, $lvl:expr, $($($key:tt).+ = $val:expr),+; $fmt:literal) => {{
+        $crate::record!($log, $lvl, $($($key).+ = $val),+; $fmt, )
     }};
 
     // record!(logkit::LEVEL_TRACE, name = "Alice", age = 20; "Hi {}! I know, time flies. I've visited {} countries since then.", "Bob", 3);
     // {"msg":"Hi Bob! I know, time flies. I've visited 3 countries since then.","name":"Alice","age":20}
-    ($log:expr, $lvl:expr, $($key:tt = $val:expr),+; $fmt:literal, $($arg:tt)*) => {{
+    ($log:expr, $lvl:expr, $($($key:tt).+ = $val:expr),+; $fmt:literal, $($arg:tt)*) => {{
         if let Some(mut record) = $log.spawn($lvl, $crate::source!()) {
             record.append("msg", &format!($fmt, $($arg)*));
-            $(record.append(stringify!($key), &$val);)*
+            $(record.append_nested(&[$(stringify!($key)),+], &$val);)*
 
             $log.flush(record);
         }
     }};
-}
\ No newline at end of file
+}
+
+/// Create fields encoded in advance
+///
+/// ```
+/// #[macro_use] extern crate logkit;
+///
+/// let fields = fields!(service = "checkout", version = "1.2.0");
+/// assert_eq!(fields.len(), 2);
+///
+/// let nested = fields!(service.name = "checkout", service.version = "1.2.0");
+/// assert_eq!(nested.len(), 1);
+///
+/// let empty = fields!();
+/// assert!(empty.is_empty());
+/// ```
+#[macro_export]
+macro_rules! fields {
+    ($($($key:tt).+ = $val:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut fields = $crate::Fields::new();
+        $(fields.append_nested(&[$(stringify!($key)),+], &$val);)*
+        fields
+    }};
+}
@@ -0,0 +1,257 @@
+//! MessagePack encoding of records
+use super::format::*;
+use super::record::*;
+use super::value::*;
+use std::borrow::Cow;
+
+/// Output MessagePack
+///
+/// Each record is encoded as a map of its fields. Maps are self-delimiting, so records written to
+/// a file or a stream one after another can be read back by MsgpackDecoder. Integers use their
+/// smallest representation, floats are encoded as float 64, and integers beyond 64 bits as strings.
+///
+/// Owned values appended with `Record::append_value` are encoded straight from their typed values,
+/// other fields are parsed back from their JSON first.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append("msg", &"hi");
+/// record.append("port", &3000);
+/// record.finish();
+///
+/// let data = record.render(&logkit::MsgpackFormat).to_vec();
+/// assert_eq!(data, b"\x82\xa3msg\xa2hi\xa4port\xcd\x0b\xb8");
+///
+/// let mut decoder = logkit::MsgpackDecoder::new(&data);
+/// assert_eq!(decoder.next().unwrap().unwrap(), logkit::Value::Object(vec![
+///     ("msg".into(), logkit::Value::Str("hi".into())),
+///     ("port".into(), logkit::Value::Uint(3000)),
+/// ]));
+/// assert!(decoder.next().is_none());
+///
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, logkit::source!());
+/// record.append_value("msg", "hi");
+/// record.append_value("port", 3000);
+/// record.finish();
+/// assert_eq!(record.render(&logkit::MsgpackFormat), b"\x82\xa3msg\xa2hi\xa4port\xcd\x0b\xb8");
+/// ```
+pub struct MsgpackFormat;
+
+impl Format for MsgpackFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        msgpack_head(buf, 0x80, 0xde, 0xdf, record.fields().count());
+
+        for field in record.fields() {
+            msgpack_str(buf, &field.key());
+            msgpack_encode(&field.value(), buf);
+        }
+    }
+}
+
+/// Encode a value to MessagePack
+///
+/// ```
+/// let mut buf = vec![];
+/// logkit::msgpack_encode(&logkit::Value::Array(vec![logkit::Value::Int(-1), logkit::Value::Null, logkit::Value::Bool(true)]), &mut buf);
+/// assert_eq!(buf, b"\x93\xff\xc0\xc3");
+/// ```
+pub fn msgpack_encode(value: &Value, buf: &mut Vec<u8>) {
+    match value {
+        Value::Null => buf.push(0xc0),
+        Value::Bool(val) => buf.push(if *val { 0xc3 } else { 0xc2 }),
+        Value::Uint(val) => match *val {
+            0..=0x7f => buf.push(*val as u8),
+            0x80..=0xff => { buf.push(0xcc); buf.push(*val as u8); }
+            0x100..=0xffff => { buf.push(0xcd); buf.extend_from_slice(&(*val as u16).to_be_bytes()); }
+            0x10000..=0xffff_ffff => { buf.push(0xce); buf.extend_from_slice(&(*val as u32).to_be_bytes()); }
+            _ => { buf.push(0xcf); buf.extend_from_slice(&val.to_be_bytes()); }
+        },
+        Value::Int(val) => match *val {
+            0.. => msgpack_encode(&Value::Uint(*val as u64), buf),
+            -32..=-1 => buf.push(*val as i8 as u8),
+            -0x80..=-33 => { buf.push(0xd0); buf.push(*val as i8 as u8); }
+            -0x8000..=-0x81 => { buf.push(0xd1); buf.extend_from_slice(&(*val as i16).to_be_bytes()); }
+            -0x8000_0000..=-0x8001 => { buf.push(0xd2); buf.extend_from_slice(&(*val as i32).to_be_bytes()); }
+            _ => { buf.push(0xd3); buf.extend_from_slice(&val.to_be_bytes()); }
+        },
+        Value::Float(val) => { buf.push(0xcb); buf.extend_from_slice(&val.to_be_bytes()); }
+        Value::BigInt(val) | Value::Str(val) => msgpack_str(buf, val),
+        Value::Array(val) => {
+            msgpack_head(buf, 0x90, 0xdc, 0xdd, val.len());
+            val.iter().for_each(|item| msgpack_encode(item, buf));
+        }
+        Value::Object(val) => {
+            msgpack_head(buf, 0x80, 0xde, 0xdf, val.len());
+
+            for (key, item) in val {
+                msgpack_str(buf, key);
+                msgpack_encode(item, buf);
+            }
+        }
+        Value::Raw(_) => msgpack_encode(&value.parsed(), buf),
+    }
+}
+
+#[inline]
+fn msgpack_str(buf: &mut Vec<u8>, val: &str) {
+    match val.len() {
+        0..=31 => buf.push(0xa0 | val.len() as u8),
+        32..=0xff => { buf.push(0xd9); buf.push(val.len() as u8); }
+        0x100..=0xffff => { buf.push(0xda); buf.extend_from_slice(&(val.len() as u16).to_be_bytes()); }
+        _ => { buf.push(0xdb); buf.extend_from_slice(&(val.len() as u32).to_be_bytes()); }
+    }
+
+    buf.extend_from_slice(val.as_bytes());
+}
+
+#[inline]
+fn msgpack_head(buf: &mut Vec<u8>, fix: u8, b16: u8, b32: u8, len: usize) {
+    match len {
+        0..=15 => buf.push(fix | len as u8),
+        16..=0xffff => { buf.push(b16); buf.extend_from_slice(&(len as u16).to_be_bytes()); }
+        _ => { buf.push(b32); buf.extend_from_slice(&(len as u32).to_be_bytes()); }
+    }
+}
+
+/// Read values back from MessagePack
+///
+/// Iterates over the values stored one after another in data, like the output of MsgpackFormat.
+/// The iteration stops after an error.
+///
+/// ```
+/// let data = b"\x81\xa3msg\xa2hi\x81\xa3msg\xa3bye";
+/// let values: Vec<_> = logkit::MsgpackDecoder::new(data).collect::<anyhow::Result<_>>().unwrap();
+/// assert_eq!(values.len(), 2);
+/// assert_eq!(values[1], logkit::Value::Object(vec![("msg".into(), logkit::Value::Str("bye".into()))]));
+///
+/// assert!(logkit::MsgpackDecoder::new(b"\x81\xa3msg").next().unwrap().is_err());
+/// assert!(logkit::MsgpackDecoder::new(b"\xdb\xff\xff\xff\xff").next().unwrap().is_err());
+/// ```
+pub struct MsgpackDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MsgpackDecoder<'a> {
+    /// Create a decoder over data
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {data, pos: 0}
+    }
+
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        match self.pos.checked_add(len).and_then(|end| self.data.get(self.pos..end)) {
+            Some(val) => { self.pos += len; Ok(val) }
+            None => anyhow::bail!("unexpected end of msgpack data"),
+        }
+    }
+
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uint(&mut self, len: usize) -> anyhow::Result<u64> {
+        Ok(self.take(len)?.iter().fold(0, |acc, ch| acc << 8 | *ch as u64))
+    }
+
+    fn int(&mut self, len: usize) -> anyhow::Result<i64> {
+        let bits = 64 - len as u32 * 8;
+        Ok(((self.uint(len)? << bits) as i64) >> bits)
+    }
+
+    fn str(&mut self, len: usize) -> anyhow::Result<Cow<'a, str>> {
+        Ok(String::from_utf8_lossy(self.take(len)?))
+    }
+
+    fn value(&mut self, depth: usize) -> anyhow::Result<Value<'a>> {
+        if depth > MSGPACK_DEPTH {
+            anyhow::bail!("msgpack data nested too deep");
+        }
+
+        let head = self.byte()?;
+
+        Ok(match head {
+            0x00..=0x7f => Value::Uint(head as u64),
+            0x80..=0x8f => self.object((head & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.array((head & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => Value::Str(self.str((head & 0x1f) as usize)?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4 => { let len = self.uint(1)? as usize; Value::Str(self.str(len)?) }
+            0xc5 => { let len = self.uint(2)? as usize; Value::Str(self.str(len)?) }
+            0xc6 => { let len = self.uint(4)? as usize; Value::Str(self.str(len)?) }
+            0xca => Value::Float(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => Value::Float(f64::from_bits(self.uint(8)?)),
+            0xcc => Value::Uint(self.uint(1)?),
+            0xcd => Value::Uint(self.uint(2)?),
+            0xce => Value::Uint(self.uint(4)?),
+            0xcf => Value::Uint(self.uint(8)?),
+            0xd0 => msgpack_int(self.int(1)?),
+            0xd1 => msgpack_int(self.int(2)?),
+            0xd2 => msgpack_int(self.int(4)?),
+            0xd3 => msgpack_int(self.int(8)?),
+            0xd9 => { let len = self.uint(1)? as usize; Value::Str(self.str(len)?) }
+            0xda => { let len = self.uint(2)? as usize; Value::Str(self.str(len)?) }
+            0xdb => { let len = self.uint(4)? as usize; Value::Str(self.str(len)?) }
+            0xdc => { let len = self.uint(2)? as usize; self.array(len, depth)? }
+            0xdd => { let len = self.uint(4)? as usize; self.array(len, depth)? }
+            0xde => { let len = self.uint(2)? as usize; self.object(len, depth)? }
+            0xdf => { let len = self.uint(4)? as usize; self.object(len, depth)? }
+            0xe0..=0xff => Value::Int(head as i8 as i64),
+            _ => anyhow::bail!("unsupported msgpack type 0x{:02x}", head),
+        })
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> anyhow::Result<Value<'a>> {
+        let mut items = Vec::with_capacity(len.min(self.data.len().saturating_sub(self.pos)));
+
+        for _ in 0..len {
+            items.push(self.value(depth + 1)?);
+        }
+
+        Ok(Value::Array(items))
+    }
+
+    fn object(&mut self, len: usize, depth: usize) -> anyhow::Result<Value<'a>> {
+        let mut items = Vec::with_capacity(len.min(self.data.len().saturating_sub(self.pos)));
+
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::Str(key) => key,
+                _ => anyhow::bail!("msgpack map key is not a string"),
+            };
+
+            items.push((key, self.value(depth + 1)?));
+        }
+
+        Ok(Value::Object(items))
+    }
+}
+
+impl<'a> Iterator for MsgpackDecoder<'a> {
+    type Item = anyhow::Result<Value<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let value = self.value(0);
+
+        if value.is_err() {
+            self.pos = self.data.len();
+        }
+
+        Some(value)
+    }
+}
+
+const MSGPACK_DEPTH: usize = 128;
+
+#[inline]
+fn msgpack_int(val: i64) -> Value<'static> {
+    match val {
+        0.. => Value::Uint(val as u64),
+        _ => Value::Int(val),
+    }
+}
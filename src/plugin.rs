@@ -1,11 +1,33 @@
 //! Plugin trait and some built-in plugins
 use super::define::*;
 use super::record::*;
+use super::value::*;
+use std::borrow::Cow;
 
 /// The Plugin Trait
 ///
 /// A plugin can be used to customize a record. You can append additional fields to a record before
-/// or after the `msg` field.
+/// or after the `msg` field. In `post`, the fields can also be looked up, removed or replaced, and
+/// the level can be changed, before targets see the record.
+///
+/// ```
+/// struct RedactPlugin;
+///
+/// impl logkit::Plugin for RedactPlugin {
+///     fn post(&self, record: &mut logkit::Record) -> bool {
+///         record.remove("password");
+///
+///         if record.get("status").and_then(|f| f.value().as_f64()).is_some_and(|val| val >= 500.0) {
+///             record.set_level(logkit::LEVEL_ERROR);
+///         }
+///
+///         true
+///     }
+/// }
+///
+/// let mut logger = logkit::Logger::new(Some(&logkit::StderrTarget));
+/// logger.mount(RedactPlugin);
+/// ```
 ///
 /// You can terminate the log processing in advance, simply return `false` in `pre` or `post`.
 #[allow(unused_variables)]
@@ -58,6 +80,8 @@ impl Plugin for LevelPlugin {
 }
 
 /// Add a rfc3339 datetime string to a record
+///
+/// The time is taken from `Record::time`, the moment the record was created.
 pub struct TimePlugin {
     /// time format
     pub format: chrono::SecondsFormat,
@@ -104,7 +128,7 @@ impl TimePlugin {
 impl Plugin for TimePlugin {
     #[inline]
     fn pre(&self, record: &mut Record) -> bool {
-        let now = chrono::Local::now();
+        let now = record.datetime();
         record.append("time", &now.to_rfc3339_opts(self.format, false));
         true
     }
@@ -112,19 +136,340 @@ impl Plugin for TimePlugin {
 
 /// Add source info to a record
 ///
+/// By default the file and line are added as a string, options can be chained to add more info,
+/// trim the paths or output an object.
+///
 /// ```json,no_run
 /// {"src":"examples/hello_world.rs:9"}
 /// ```
-pub struct SourcePlugin;
+///
+/// ```
+/// let plugin = logkit::SourcePlugin::new().with_column().with_function().strip_prefix("/home/ci/repo/").nested();
+///
+/// let source = logkit::Source::new("/home/ci/repo/src/net.rs", 9, 5).with_module("app::net").with_function("serve");
+/// let mut record = logkit::Record::new(logkit::LEVEL_INFO, source);
+///
+/// assert!(logkit::Plugin::post(&plugin, &mut record));
+/// record.finish();
+/// assert_eq!(String::from_utf8_lossy(record.buffer()), "{\"src\":{\"file\":\"src/net.rs\",\"line\":9,\"column\":5,\"function\":\"serve\"}}\n");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct SourcePlugin {
+    /// add the column
+    pub column: bool,
+
+    /// add the module path
+    pub module: bool,
+
+    /// add the function name
+    pub function: bool,
+
+    /// path prefixes and their replacements, the first match is used
+    pub prefixes: Vec<(String, String)>,
+
+    /// output an object instead of a string
+    pub nested: bool,
+}
+
+/// SourcePlugin with the default options, usable as a value like before it had options
+///
+/// ```
+/// let mut logger = logkit::Logger::new(None);
+/// logger.mount(logkit::SourcePlugin);
+/// ```
+#[allow(non_upper_case_globals)]
+pub const SourcePlugin: SourcePlugin = SourcePlugin::new();
+
+impl SourcePlugin {
+    /// Add the file and line as a string
+    pub const fn new() -> Self {
+        Self {column: false, module: false, function: false, prefixes: vec![], nested: false}
+    }
+
+    /// Add the column
+    pub fn with_column(mut self) -> Self {
+        self.column = true;
+        self
+    }
+
+    /// Add the module path
+    ///
+    /// ```json,no_run
+    /// {"src":"app::net src/net.rs:9"}
+    /// ```
+    pub fn with_module(mut self) -> Self {
+        self.module = true;
+        self
+    }
+
+    /// Add the function name
+    ///
+    /// ```json,no_run
+    /// {"src":"serve src/net.rs:9"}
+    /// ```
+    pub fn with_function(mut self) -> Self {
+        self.function = true;
+        self
+    }
+
+    /// Remove a prefix from the paths
+    pub fn strip_prefix(self, prefix: impl Into<String>) -> Self {
+        self.remap_prefix(prefix, "")
+    }
+
+    /// Replace a prefix of the paths, like a machine-dependent directory with the repository name
+    pub fn remap_prefix(mut self, prefix: impl Into<String>, to: impl Into<String>) -> Self {
+        self.prefixes.push((prefix.into(), to.into()));
+        self
+    }
+
+    /// Output an object instead of a string
+    ///
+    /// ```json,no_run
+    /// {"src":{"file":"src/net.rs","line":9}}
+    /// ```
+    pub fn nested(mut self) -> Self {
+        self.nested = true;
+        self
+    }
+
+    /// Trim or remap a path by the prefixes
+    ///
+    /// ```
+    /// let plugin = logkit::SourcePlugin::new().remap_prefix("/home/ci/work/", "repo/");
+    /// assert_eq!(plugin.path("/home/ci/work/src/main.rs"), "repo/src/main.rs");
+    /// assert_eq!(plugin.path("src/main.rs"), "src/main.rs");
+    /// ```
+    pub fn path<'a>(&self, file: &'a str) -> Cow<'a, str> {
+        for (prefix, to) in &self.prefixes {
+            if let Some(rest) = file.strip_prefix(prefix.as_str()) {
+                return match to.is_empty() {
+                    true => Cow::Borrowed(rest),
+                    false => Cow::Owned(format!("{}{}", to, rest)),
+                };
+            }
+        }
+
+        Cow::Borrowed(file)
+    }
+}
 
 impl Plugin for SourcePlugin {
     #[inline]
     fn post(&self, record: &mut Record) -> bool {
-        record.append("src", &format!("{}:{}", record.source().file, record.source().line));
+        let source = record.source().clone();
+        let file = self.path(source.file);
+
+        if self.nested {
+            record.group("src", |g| {
+                g.append("file", &file.as_ref());
+                g.append("line", &source.line);
+
+                if self.column {
+                    g.append("column", &source.column);
+                }
+
+                if self.module {
+                    g.append("module", &source.module());
+                }
+
+                if self.function {
+                    g.append("function", &source.function());
+                }
+            });
+
+            return true;
+        }
+
+        let mut text = String::new();
+
+        match (self.module, self.function) {
+            (true, true) => text.push_str(&format!("{}::{} ", source.module(), source.function())),
+            (true, false) => text.push_str(&format!("{} ", source.module())),
+            (false, true) => text.push_str(&format!("{} ", source.function())),
+            (false, false) => {}
+        }
+
+        match self.column {
+            true => text.push_str(&format!("{}:{}:{}", file, source.line, source.column)),
+            false => text.push_str(&format!("{}:{}", file, source.line)),
+        }
+
+        record.append("src", &text);
+        true
+    }
+}
+
+/// Add the identity of the current thread to a record
+///
+/// The OS thread id and the thread name are read once per thread and cached in thread-locals.
+/// Unnamed threads have no `thread_name`. With the `tokio` feature, the id of the current tokio
+/// task is added as `task_id` when there is one.
+///
+/// ```json,no_run
+/// {"thread_id":12345,"thread_name":"main","task_id":7}
+/// ```
+///
+/// ```
+/// let mut logger = logkit::Logger::new(None);
+/// logger.mount(logkit::ThreadPlugin);
+///
+/// if let Some(mut record) = logger.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+///     record.finish();
+///     assert!(record.get("thread_id").is_some_and(|f| matches!(*f.value(), logkit::Value::Uint(_))));
+/// }
+/// ```
+pub struct ThreadPlugin;
+
+struct ThreadIdentity {
+    id: u64,
+    name: Option<String>, // encoded JSON string
+}
+
+thread_local! {
+    static THREAD_IDENTITY: ThreadIdentity = ThreadIdentity {
+        id: thread_os_id(),
+        name: std::thread::current().name().map(|name| {
+            let mut buf = vec![];
+            name.encode(&mut buf);
+            String::from_utf8_lossy(&buf).into_owned()
+        }),
+    };
+}
+
+impl Plugin for ThreadPlugin {
+    #[inline]
+    fn pre(&self, record: &mut Record) -> bool {
+        // skipped while the thread locals are torn down, e.g. logging in the drop of another one
+        let _ = THREAD_IDENTITY.try_with(|identity| {
+            record.append("thread_id", &identity.id);
+
+            if let Some(name) = &identity.name {
+                record.append("thread_name", &Value::Raw(Cow::Borrowed(name)));
+            }
+        });
+
+        #[cfg(feature = "tokio")]
+        if let Some(id) = tokio::task::try_id() {
+            record.append("task_id", &TaskId(id));
+        }
+
         true
     }
 }
 
+#[cfg(feature = "tokio")]
+struct TaskId(tokio::task::Id);
+
+#[cfg(feature = "tokio")]
+impl Encode for TaskId {
+    #[inline]
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let _ = write!(buf, "{}", self.0);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn thread_os_id() -> u64 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u64 }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn thread_os_id() -> u64 {
+    let mut tid = 0;
+    unsafe { libc::pthread_threadid_np(0, &mut tid); }
+    tid
+}
+
+#[cfg(windows)]
+fn thread_os_id() -> u64 {
+    extern "system" {
+        fn GetCurrentThreadId() -> u32;
+    }
+
+    unsafe { GetCurrentThreadId() as u64 }
+}
+
+// fall back to the id assigned by Rust
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", windows)))]
+fn thread_os_id() -> u64 {
+    let id = format!("{:?}", std::thread::current().id());
+    id.trim_start_matches("ThreadId(").trim_end_matches(')').parse().unwrap_or_default()
+}
+
+/// Add the metadata of the process to a record
+///
+/// The hostname, pid, executable name and version are read once and encoded in advance, then
+/// copied into each record as bytes.
+///
+/// ```json,no_run
+/// {"hostname":"web-1","pid":12345,"exe":"server","version":"1.2.0"}
+/// ```
+///
+/// ```
+/// let mut logger = logkit::Logger::new(None);
+/// logger.mount(logkit::ProcessPlugin::new(env!("CARGO_PKG_VERSION")));
+///
+/// if let Some(mut record) = logger.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+///     record.finish();
+///     assert_eq!(record.get("pid").map(|f| f.value().into_owned()), Some(logkit::Value::Uint(std::process::id() as u64)));
+///     assert_eq!(record.get("version").map(|f| f.value().into_owned()), Some(logkit::Value::Str(env!("CARGO_PKG_VERSION").into())));
+/// }
+/// ```
+pub struct ProcessPlugin {
+    /// encoded fields
+    pub fields: Fields,
+}
+
+impl ProcessPlugin {
+    /// Create a ProcessPlugin with the version of the application, like `env!("CARGO_PKG_VERSION")`
+    pub fn new(version: &str) -> Self {
+        let mut fields = Fields::new();
+
+        if let Some(hostname) = process_hostname() {
+            fields.append("hostname", &hostname);
+        }
+
+        fields.append("pid", &std::process::id());
+
+        let exe = std::env::current_exe().ok().and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()));
+
+        if let Some(exe) = exe {
+            fields.append("exe", &exe);
+        }
+
+        fields.append("version", &version);
+
+        Self {fields}
+    }
+}
+
+impl Plugin for ProcessPlugin {
+    #[inline]
+    fn pre(&self, record: &mut Record) -> bool {
+        record.extend(&self.fields);
+        true
+    }
+}
+
+#[cfg(unix)]
+fn process_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+
+    match unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } {
+        0 => {
+            let len = buf.iter().position(|ch| *ch == 0).unwrap_or(buf.len());
+            Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn process_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
 /// Represent a stack trace frame
 #[derive(Debug, Default, Clone)]
 pub struct StackFrame {
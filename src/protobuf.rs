@@ -0,0 +1,384 @@
+//! Protobuf encoding of records
+use super::define::*;
+use super::format::*;
+use super::record::*;
+use super::value::*;
+use std::borrow::Cow;
+
+/// The schema of records written by ProtobufFormat
+///
+/// The same file is published as `proto/logkit.proto` in the crate, for consumers in other
+/// languages.
+pub const PROTOBUF_SCHEMA: &str = include_str!("../proto/logkit.proto");
+
+/// Output Protobuf
+///
+/// Each record is encoded as the `logkit.Record` message of `PROTOBUF_SCHEMA`, prefixed with its
+/// size as a varint. The level and source are taken from the record, the time and message from the
+/// `time` and `msg` fields, and all other fields become typed attributes. Without a `time` field,
+/// the time the record was created is used. Owned values appended with `Record::append_value` are
+/// encoded straight from their typed values, other fields are parsed back from their JSON first.
+///
+/// ```
+/// let mut record = logkit::Record::new(logkit::LEVEL_WARN, logkit::source!());
+/// record.append("time", &"2024-01-03T11:01:00+08:00");
+/// record.append("msg", &"hello");
+/// record.append("port", &3000);
+/// record.finish();
+///
+/// let data = record.render(&logkit::ProtobufFormat).to_vec();
+///
+/// let mut decoder = logkit::ProtobufDecoder::new(&data);
+/// let decoded = decoder.next().unwrap().unwrap();
+/// assert_eq!(decoded.time_unix_nano, 1704250860_000_000_000);
+/// assert_eq!(decoded.level, logkit::LEVEL_WARN);
+/// assert_eq!(decoded.file, record.source().file);
+/// assert_eq!(decoded.message, "hello");
+/// assert_eq!(decoded.attributes, vec![("port".into(), logkit::Value::Uint(3000))]);
+/// assert!(decoder.next().is_none());
+/// ```
+pub struct ProtobufFormat;
+
+impl Format for ProtobufFormat {
+    fn format(&self, record: &Record, buf: &mut Vec<u8>) {
+        let mut body = vec![];
+
+        let time = record.fields()
+            .find(|field| field.key() == "time")
+            .and_then(|field| field.value().as_str().and_then(|val| chrono::DateTime::parse_from_rfc3339(val).ok()))
+            .map_or_else(|| record.datetime().timestamp_nanos_opt(), |val| val.timestamp_nanos_opt())
+            .unwrap_or_default();
+
+        if time != 0 {
+            protobuf_key(&mut body, 1, 0);
+            protobuf_varint(&mut body, time as u64);
+        }
+
+        if record.level() != 0 {
+            protobuf_key(&mut body, 2, 0);
+            protobuf_varint(&mut body, record.level() as i64 as u64);
+        }
+
+        let mut source = vec![];
+        protobuf_bytes(&mut source, 1, record.source().file.as_bytes());
+        protobuf_key(&mut source, 2, 0);
+        protobuf_varint(&mut source, record.source().line as u64);
+        protobuf_key(&mut source, 3, 0);
+        protobuf_varint(&mut source, record.source().column as u64);
+        protobuf_bytes(&mut body, 3, &source);
+
+        for field in record.fields() {
+            let key = field.key();
+
+            match key.as_ref() {
+                "time" | "level" | "src" => {}
+                "msg" => match field.value().as_ref() {
+                    Value::Str(val) => protobuf_bytes(&mut body, 4, val.as_bytes()),
+                    _ => protobuf_bytes(&mut body, 4, &field.json()),
+                },
+                _ => protobuf_attribute(&mut body, 5, &key, &field.value()),
+            }
+        }
+
+        protobuf_varint(buf, body.len() as u64);
+        buf.extend_from_slice(&body);
+    }
+}
+
+#[inline]
+fn protobuf_varint(buf: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        buf.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+
+    buf.push(val as u8);
+}
+
+#[inline]
+fn protobuf_key(buf: &mut Vec<u8>, field: u32, wire: u8) {
+    protobuf_varint(buf, (field as u64) << 3 | wire as u64);
+}
+
+#[inline]
+fn protobuf_bytes(buf: &mut Vec<u8>, field: u32, val: &[u8]) {
+    protobuf_key(buf, field, 2);
+    protobuf_varint(buf, val.len() as u64);
+    buf.extend_from_slice(val);
+}
+
+fn protobuf_attribute(buf: &mut Vec<u8>, field: u32, key: &str, value: &Value) {
+    let mut body = vec![];
+    let mut inner = vec![];
+
+    protobuf_bytes(&mut body, 1, key.as_bytes());
+    protobuf_value(&mut inner, value);
+    protobuf_bytes(&mut body, 2, &inner);
+    protobuf_bytes(buf, field, &body);
+}
+
+fn protobuf_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => { protobuf_key(buf, 1, 0); protobuf_varint(buf, 1); }
+        Value::Bool(val) => { protobuf_key(buf, 2, 0); protobuf_varint(buf, *val as u64); }
+        Value::Int(val) => { protobuf_key(buf, 3, 0); protobuf_varint(buf, ((val << 1) ^ (val >> 63)) as u64); }
+        Value::Uint(val) => { protobuf_key(buf, 4, 0); protobuf_varint(buf, *val); }
+        Value::Float(val) => { protobuf_key(buf, 5, 1); buf.extend_from_slice(&val.to_le_bytes()); }
+        Value::Str(val) => protobuf_bytes(buf, 6, val.as_bytes()),
+        Value::BigInt(val) => protobuf_bytes(buf, 7, val.as_bytes()),
+        Value::Array(val) => {
+            let mut body = vec![];
+
+            for item in val {
+                let mut inner = vec![];
+                protobuf_value(&mut inner, item);
+                protobuf_bytes(&mut body, 1, &inner);
+            }
+
+            protobuf_bytes(buf, 8, &body);
+        }
+        Value::Object(val) => {
+            let mut body = vec![];
+
+            for (key, item) in val {
+                protobuf_attribute(&mut body, 1, key, item);
+            }
+
+            protobuf_bytes(buf, 9, &body);
+        }
+        Value::Raw(_) => protobuf_value(buf, &value.parsed()),
+    }
+}
+
+/// Record decoded from Protobuf
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProtobufRecord<'a> {
+    /// unix time in nanoseconds
+    pub time_unix_nano: i64,
+
+    /// log level
+    pub level: Level,
+
+    /// source file
+    pub file: Cow<'a, str>,
+
+    /// source line
+    pub line: u32,
+
+    /// source column
+    pub column: u32,
+
+    /// message
+    pub message: Cow<'a, str>,
+
+    /// other fields
+    pub attributes: Vec<(Cow<'a, str>, Value<'a>)>,
+}
+
+/// Read records back from Protobuf
+///
+/// Iterates over the length-delimited records written by ProtobufFormat. Unknown fields are
+/// skipped. The iteration stops after an error.
+///
+/// ```
+/// assert!(logkit::ProtobufDecoder::new(b"\x05\x10\x02").next().unwrap().is_err());
+/// ```
+pub struct ProtobufDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtobufDecoder<'a> {
+    /// Create a decoder over data
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {data, pos: 0}
+    }
+
+    fn record(data: &'a [u8]) -> anyhow::Result<ProtobufRecord<'a>> {
+        let mut reader = ProtobufReader {data, pos: 0};
+        let mut record = ProtobufRecord::default();
+
+        while let Some((field, wire)) = reader.key()? {
+            match (field, wire) {
+                (1, 0) => record.time_unix_nano = reader.varint()? as i64,
+                (2, 0) => record.level = reader.varint()? as i64 as Level,
+                (3, 2) => {
+                    let mut source = ProtobufReader {data: reader.bytes()?, pos: 0};
+
+                    while let Some((field, wire)) = source.key()? {
+                        match (field, wire) {
+                            (1, 2) => record.file = String::from_utf8_lossy(source.bytes()?),
+                            (2, 0) => record.line = source.varint()? as u32,
+                            (3, 0) => record.column = source.varint()? as u32,
+                            (_, wire) => source.skip(wire)?,
+                        }
+                    }
+                }
+                (4, 2) => record.message = String::from_utf8_lossy(reader.bytes()?),
+                (5, 2) => record.attributes.push(Self::attribute(reader.bytes()?, 0)?),
+                (_, wire) => reader.skip(wire)?,
+            }
+        }
+
+        Ok(record)
+    }
+
+    fn attribute(data: &'a [u8], depth: usize) -> anyhow::Result<(Cow<'a, str>, Value<'a>)> {
+        let mut reader = ProtobufReader {data, pos: 0};
+        let mut key = Cow::Borrowed("");
+        let mut value = Value::Null;
+
+        while let Some((field, wire)) = reader.key()? {
+            match (field, wire) {
+                (1, 2) => key = String::from_utf8_lossy(reader.bytes()?),
+                (2, 2) => value = Self::value(reader.bytes()?, depth + 1)?,
+                (_, wire) => reader.skip(wire)?,
+            }
+        }
+
+        Ok((key, value))
+    }
+
+    fn value(data: &'a [u8], depth: usize) -> anyhow::Result<Value<'a>> {
+        if depth > PROTOBUF_DEPTH {
+            anyhow::bail!("protobuf data nested too deep");
+        }
+
+        let mut reader = ProtobufReader {data, pos: 0};
+        let mut value = Value::Null;
+
+        while let Some((field, wire)) = reader.key()? {
+            value = match (field, wire) {
+                (1, 0) => { reader.varint()?; Value::Null }
+                (2, 0) => Value::Bool(reader.varint()? != 0),
+                (3, 0) => {
+                    let val = reader.varint()?;
+                    match ((val >> 1) as i64) ^ -((val & 1) as i64) {
+                        val @ 0.. => Value::Uint(val as u64),
+                        val => Value::Int(val),
+                    }
+                }
+                (4, 0) => Value::Uint(reader.varint()?),
+                (5, 1) => Value::Float(f64::from_le_bytes(reader.fixed()?)),
+                (6, 2) => Value::Str(String::from_utf8_lossy(reader.bytes()?)),
+                (7, 2) => Value::BigInt(String::from_utf8_lossy(reader.bytes()?)),
+                (8, 2) => {
+                    let mut array = ProtobufReader {data: reader.bytes()?, pos: 0};
+                    let mut items = vec![];
+
+                    while let Some((field, wire)) = array.key()? {
+                        match (field, wire) {
+                            (1, 2) => items.push(Self::value(array.bytes()?, depth + 1)?),
+                            (_, wire) => array.skip(wire)?,
+                        }
+                    }
+
+                    Value::Array(items)
+                }
+                (9, 2) => {
+                    let mut object = ProtobufReader {data: reader.bytes()?, pos: 0};
+                    let mut items = vec![];
+
+                    while let Some((field, wire)) = object.key()? {
+                        match (field, wire) {
+                            (1, 2) => items.push(Self::attribute(object.bytes()?, depth + 1)?),
+                            (_, wire) => object.skip(wire)?,
+                        }
+                    }
+
+                    Value::Object(items)
+                }
+                (_, wire) => { reader.skip(wire)?; continue; }
+            };
+        }
+
+        Ok(value)
+    }
+}
+
+impl<'a> Iterator for ProtobufDecoder<'a> {
+    type Item = anyhow::Result<ProtobufRecord<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let mut reader = ProtobufReader {data: self.data, pos: self.pos};
+        let record = reader.bytes().and_then(Self::record);
+
+        self.pos = match record.is_ok() {
+            true => reader.pos,
+            false => self.data.len(),
+        };
+
+        Some(record)
+    }
+}
+
+const PROTOBUF_DEPTH: usize = 128;
+
+struct ProtobufReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtobufReader<'a> {
+    fn varint(&mut self) -> anyhow::Result<u64> {
+        let mut val = 0;
+
+        for shift in (0..64).step_by(7) {
+            let Some(ch) = self.data.get(self.pos) else {
+                anyhow::bail!("unexpected end of protobuf data");
+            };
+
+            self.pos += 1;
+            val |= ((ch & 0x7f) as u64) << shift;
+
+            if ch & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+
+        anyhow::bail!("protobuf varint too long")
+    }
+
+    fn key(&mut self) -> anyhow::Result<Option<(u64, u8)>> {
+        match self.pos < self.data.len() {
+            true => { let key = self.varint()?; Ok(Some((key >> 3, (key & 7) as u8))) }
+            false => Ok(None),
+        }
+    }
+
+    fn take(&mut self, len: u64) -> anyhow::Result<&'a [u8]> {
+        let end = usize::try_from(len).ok().and_then(|len| self.pos.checked_add(len));
+
+        match end.and_then(|end| self.data.get(self.pos..end)) {
+            Some(val) => { self.pos += val.len(); Ok(val) }
+            None => anyhow::bail!("unexpected end of protobuf data"),
+        }
+    }
+
+    fn bytes(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.varint()?;
+        self.take(len)
+    }
+
+    fn fixed(&mut self) -> anyhow::Result<[u8; 8]> {
+        let mut val = [0; 8];
+        val.copy_from_slice(self.take(8)?);
+        Ok(val)
+    }
+
+    fn skip(&mut self, wire: u8) -> anyhow::Result<()> {
+        match wire {
+            0 => { self.varint()?; }
+            1 => { self.take(8)?; }
+            2 => { self.bytes()?; }
+            5 => { self.take(4)?; }
+            _ => anyhow::bail!("unsupported protobuf wire type {}", wire),
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 //! Record represent a single log entry
 use super::define::*;
+use super::format::*;
 use super::source::*;
+use super::value::*;
+use std::borrow::Cow;
+use std::time::{Instant, SystemTime};
 
 /// Log Record
 ///
@@ -11,8 +15,35 @@ use super::source::*;
 #[derive(Debug, Clone)]
 pub struct Record {
     level: Level,
+    time: SystemTime,
+    instant: Instant,
     buffer: Vec<u8>,
     source: Source,
+    fields: Vec<Span>,       // position of each field in buffer
+    cache: Vec<u8>,          // output of non-json formats
+    metrics: Vec<Metric>,    // fields marked as metrics
+    dimensions: Vec<String>, // fields marked as metric dimensions
+    nested: Vec<(usize, usize)>, // key and '{' of each object opened by the last append_nested
+    plugin: bool,                // fields are being appended by plugins
+    values: Vec<Value<'static>>, // typed fields, encoded when the record is finished
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    key: usize,
+    val: usize,
+    end: usize,
+    plugin: bool,
+    typed: Option<usize>, // index of the typed value, not encoded yet if val == end
+}
+
+impl Span {
+    // decode the key from the buffer
+    #[inline]
+    fn name<'a>(&self, buffer: &'a [u8]) -> Cow<'a, str> {
+        let key = &buffer[self.key..self.val - 1];
+        decode_str(key).unwrap_or_else(|| String::from_utf8_lossy(key))
+    }
 }
 
 impl Record {
@@ -24,7 +55,7 @@ impl Record {
     /// ```
     #[inline]
     pub fn new(level: Level, source: Source) -> Self {
-        let mut obj = Self {level, buffer: vec![], source};
+        let mut obj = Self {level, time: SystemTime::now(), instant: Instant::now(), buffer: vec![], source, fields: vec![], cache: vec![], metrics: vec![], dimensions: vec![], nested: vec![], plugin: false, values: vec![]};
         obj.buffer.push(b'{');
         obj
     }
@@ -41,8 +72,16 @@ impl Record {
     #[inline]
     pub fn set(mut record: Record, level: Level, source: Source) -> Self {
         record.level = level;
+        record.time = SystemTime::now();
+        record.instant = Instant::now();
         record.buffer.truncate(1); // preserve '{'
         record.source = source;
+        record.fields.clear();
+        record.metrics.clear();
+        record.dimensions.clear();
+        record.nested.clear();
+        record.plugin = false;
+        record.values.clear();
         record
     }
 
@@ -52,6 +91,32 @@ impl Record {
         self.level
     }
 
+    /// Time when the record was created
+    ///
+    /// The clock is read once when `Logger::spawn` creates the record, plugins and formats share
+    /// this timestamp instead of reading the clock again.
+    #[inline]
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Time when the record was created in local time zone
+    ///
+    /// ```
+    /// let record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// assert_eq!(std::time::SystemTime::from(record.datetime()), record.time());
+    /// ```
+    #[inline]
+    pub fn datetime(&self) -> chrono::DateTime<chrono::Local> {
+        self.time.into()
+    }
+
+    /// Monotonic time when the record was created, for measuring durations
+    #[inline]
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
     /// Current record's source info
     #[inline]
     pub fn source(&self) -> &Source {
@@ -62,7 +127,7 @@ impl Record {
     ///
     /// The order of fields is fixed, fields are stored in the order they are added.
     ///
-    /// Note that duplicate fields are not filtered out.
+    /// Note that duplicate fields are not filtered out here, see `dedupe`.
     ///
     /// ```
     /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
@@ -73,13 +138,482 @@ impl Record {
     /// ```
     #[inline]
     pub fn append(&mut self, key: &str, val: &impl Encode) -> &mut Self {
+        self.nested.clear();
+        let beg = self.buffer.len();
         key.encode(&mut self.buffer);
         self.buffer.push(b':');
+        let mid = self.buffer.len();
         val.encode(&mut self.buffer);
+        self.fields.push(Span {key: beg, val: mid, end: self.buffer.len(), plugin: self.plugin, typed: None});
+        self.buffer.push(b',');
+        self
+    }
+
+    /// Append fields encoded in advance, by copying their bytes
+    ///
+    /// ```
+    /// let fields = logkit::fields!(service = "checkout", version = "1.2.0");
+    ///
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.extend(&fields);
+    /// record.append("msg", &"ready");
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"service\":\"checkout\",\"version\":\"1.2.0\",\"msg\":\"ready\"}\n");
+    /// ```
+    #[inline]
+    pub fn extend(&mut self, fields: &Fields) -> &mut Self {
+        self.nested.clear();
+        let base = self.buffer.len();
+        self.buffer.extend_from_slice(&fields.buffer);
+
+        for span in &fields.spans {
+            self.fields.push(Span {key: base + span.key, val: base + span.val, end: base + span.end, plugin: self.plugin, typed: None});
+        }
+
+        self
+    }
+
+    /// Append a field as a typed value
+    ///
+    /// A value which borrows nothing is kept as it is until the record is finished, then encoded to
+    /// JSON, and formats read it without parsing JSON. A borrowed value is encoded at once instead
+    /// of being copied. Use `Value::Raw` to append pre-encoded JSON, invalid JSON is appended as a
+    /// string.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// let name = String::from("alice");
+    /// record.append_value("port", 3000);
+    /// record.append_value("user", logkit::Value::Raw(r#"{"id":1}"#.into()));
+    /// record.append_value("name", name.as_str());
+    /// record.append_value("bad", logkit::Value::Raw("{".into()));
+    /// assert_eq!(record.fields().next().unwrap().value().into_owned(), logkit::Value::Uint(3000));
+    ///
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"port\":3000,\"user\":{\"id\":1},\"name\":\"alice\",\"bad\":\"{\"}\n");
+    /// ```
+    #[inline]
+    pub fn append_value<'v>(&mut self, key: &str, val: impl Into<Value<'v>>) -> &mut Self {
+        self.nested.clear();
+        let mut val = val.into();
+        val.validate();
+        let beg = self.buffer.len();
+        key.encode(&mut self.buffer);
+        self.buffer.push(b':');
+        let mid = self.buffer.len();
+
+        match val.is_owned() {
+            true => {
+                self.fields.push(Span {key: beg, val: mid, end: mid, plugin: self.plugin, typed: Some(self.values.len())});
+                self.values.push(val.into_owned());
+            }
+            false => {
+                val.encode(&mut self.buffer);
+                self.fields.push(Span {key: beg, val: mid, end: self.buffer.len(), plugin: self.plugin, typed: None});
+            }
+        }
+
+        self.buffer.push(b',');
+        self
+    }
+
+    /// Append a nested object built by a closure
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.group("http", |g| {
+    ///     g.append("method", &"GET");
+    ///     g.group("response", |g| { g.append("status", &200); });
+    /// });
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"http\":{\"method\":\"GET\",\"response\":{\"status\":200}}}\n");
+    /// ```
+    #[inline]
+    pub fn group(&mut self, key: &str, build: impl FnOnce(&mut Group)) -> &mut Self {
+        self.nested.clear();
+        let beg = self.buffer.len();
+        let mid = Group::open(&mut self.buffer, key);
+        build(&mut Group {buffer: &mut self.buffer});
+        Group::close(&mut self.buffer);
+        self.fields.push(Span {key: beg, val: mid, end: self.buffer.len(), plugin: self.plugin, typed: None});
         self.buffer.push(b',');
         self
     }
 
+    /// Append a field nested in objects along the path
+    ///
+    /// Calls sharing the leading keys of their paths are merged into the same objects, this is how
+    /// the macros expand keys like `http.method`.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append_nested(&["http", "method"], &"GET");
+    /// record.append_nested(&["http", "response", "status"], &200);
+    /// record.append_nested(&["http", "response", "bytes"], &512);
+    /// record.append_nested(&["msg"], &"done");
+    /// record.append_nested(&["http", "version"], &"1.1");
+    /// record.finish();
+    /// assert_eq!(
+    ///     String::from_utf8_lossy(record.buffer().as_slice()),
+    ///     "{\"http\":{\"method\":\"GET\",\"response\":{\"status\":200,\"bytes\":512},\"version\":\"1.1\"},\"msg\":\"done\"}\n",
+    /// );
+    /// ```
+    pub fn append_nested(&mut self, path: &[&str], val: &impl Encode) -> &mut Self {
+        append_nested(&mut self.buffer, &mut self.fields, &mut self.nested, self.plugin, path, val);
+        self
+    }
+
+    /// Iterate over the fields in the order they were added
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("pid", &12345);
+    /// record.append("msg", &"think outside the box");
+    ///
+    /// let fields: Vec<_> = record.fields().map(|f| (f.key(), f.value().into_owned())).collect();
+    /// assert_eq!(fields, vec![
+    ///     ("pid".into(), logkit::Value::Uint(12345)),
+    ///     ("msg".into(), logkit::Value::Str("think outside the box".into())),
+    /// ]);
+    /// ```
+    #[inline]
+    pub fn fields(&self) -> impl Iterator<Item = Field<'_>> {
+        self.fields.iter().map(|span| Field {
+            key: &self.buffer[span.key..span.val - 1],
+            val: &self.buffer[span.val..span.end],
+            typed: span.typed.and_then(|index| self.values.get(index)),
+        })
+    }
+
+    /// Append a numeric field and mark it as a metric
+    ///
+    /// Formats like EmfFormat publish the marked fields as metrics, others output them as usual.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.metric("latency", &12.5, "Milliseconds");
+    /// assert_eq!(record.metrics()[0].key, "latency");
+    /// assert_eq!(record.metrics()[0].unit, "Milliseconds");
+    /// ```
+    #[inline]
+    pub fn metric(&mut self, key: &str, val: &impl Numeric, unit: &'static str) -> &mut Self {
+        self.metrics.push(Metric {key: key.to_string(), unit});
+        self.append(key, val)
+    }
+
+    /// Mark an appended field as a dimension of the metrics
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("service", &"checkout").dimension("service");
+    /// assert_eq!(record.dimensions(), ["service"]);
+    /// ```
+    #[inline]
+    pub fn dimension(&mut self, key: &str) -> &mut Self {
+        self.dimensions.push(key.to_string());
+        self
+    }
+
+    /// Fields marked as metrics
+    #[inline]
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// Fields marked as metric dimensions
+    #[inline]
+    pub fn dimensions(&self) -> &[String] {
+        &self.dimensions
+    }
+
+    /// Resolve fields with duplicate keys by a policy
+    ///
+    /// Fields appended by plugins and by the caller are resolved separately. A caller's field that
+    /// still collides with a plugin's field is renamed, so plugins are never shadowed silently.
+    /// Renamed fields get a suffix like `_2`, the first number that makes the key unique. Metric and
+    /// dimension marks follow the renamed fields.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("user_id", &1);
+    /// record.append("user_id", &2);
+    /// record.append("msg", &"a");
+    /// record.append("user_id", &3);
+    /// record.dedupe(logkit::DuplicatePolicy::Rename);
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"user_id\":1,\"user_id_2\":2,\"msg\":\"a\",\"user_id_3\":3}\n");
+    /// ```
+    pub fn dedupe(&mut self, policy: DuplicatePolicy) -> &mut Self {
+        if policy == DuplicatePolicy::KeepAll || self.fields.len() < 2 {
+            return self;
+        }
+
+        // sort by key then position, the fields of a key are adjacent
+        let mut order: Vec<(Cow<str>, usize)> = self.fields.iter().enumerate().map(|(index, span)| (span.name(&self.buffer), index)).collect();
+        order.sort_unstable();
+
+        if !order.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return self;
+        }
+
+        let mut edits: Vec<Edit> = self.fields.iter().map(|_| Edit::Keep).collect();
+        let mut beg = 0;
+
+        while beg < order.len() {
+            let end = beg + order[beg..].iter().take_while(|(key, _)| *key == order[beg].0).count();
+            let group = &order[beg..end];
+            beg = end;
+
+            if group.len() < 2 {
+                continue;
+            }
+
+            for plugin in [true, false] {
+                let mut same = group.iter().map(|(_, index)| *index).filter(|index| self.fields[*index].plugin == plugin).peekable();
+
+                match policy {
+                    DuplicatePolicy::KeepAll => {}
+                    DuplicatePolicy::FirstWins => same.skip(1).for_each(|index| edits[index] = Edit::Drop),
+                    DuplicatePolicy::LastWins => while let Some(index) = same.next() {
+                        if same.peek().is_some() {
+                            edits[index] = Edit::Drop;
+                        }
+                    },
+                    DuplicatePolicy::Rename => same.skip(1).for_each(|index| edits[index] = Edit::Rename),
+                }
+            }
+
+            if group.iter().any(|(_, index)| self.fields[*index].plugin) {
+                for (_, index) in group {
+                    if !self.fields[*index].plugin && matches!(edits[*index], Edit::Keep) {
+                        edits[*index] = Edit::Rename;
+                    }
+                }
+            }
+        }
+
+        drop(order);
+
+        if edits.iter().any(|edit| !matches!(edit, Edit::Keep)) {
+            self.rebuild(edits);
+        }
+
+        self
+    }
+
+    /// Look up the first field with a key
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("status", &503);
+    /// assert_eq!(record.get("status").map(|f| f.value().into_owned()), Some(logkit::Value::Uint(503)));
+    /// assert!(record.get("missing").is_none());
+    /// ```
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<Field<'_>> {
+        self.fields().find(|field| field.key() == key)
+    }
+
+    /// Remove all fields with a key, returns whether any field was removed
+    ///
+    /// Works before and after the record is finished. The metric and dimension marks of the key are
+    /// removed as well.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("user", &"alice");
+    /// record.append("password", &"secret");
+    /// record.metric("latency", &12.5, "Milliseconds");
+    /// record.finish();
+    ///
+    /// assert!(record.remove("password"));
+    /// assert!(!record.remove("password"));
+    /// assert!(record.remove("latency"));
+    /// assert!(record.metrics().is_empty());
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"user\":\"alice\"}\n");
+    /// ```
+    pub fn remove(&mut self, key: &str) -> bool {
+        let edits: Vec<Edit> = self.fields().map(|field| match field.key() == key {
+            true => Edit::Drop,
+            false => Edit::Keep,
+        }).collect();
+
+        if !edits.iter().any(|edit| matches!(edit, Edit::Drop)) {
+            return false;
+        }
+
+        self.rebuild(edits);
+        true
+    }
+
+    /// Replace the value of the first field with a key, returns whether the field was found
+    ///
+    /// Works before and after the record is finished.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("token", &"abc123");
+    /// record.append("msg", &"login");
+    ///
+    /// assert!(record.replace("token", "***"));
+    /// assert!(!record.replace("missing", 1));
+    ///
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"token\":\"***\",\"msg\":\"login\"}\n");
+    /// ```
+    pub fn replace(&mut self, key: &str, val: impl Into<Value<'static>>) -> bool {
+        let Some(index) = self.fields().position(|field| field.key() == key) else {
+            return false;
+        };
+
+        let mut edits: Vec<Edit> = self.fields.iter().map(|_| Edit::Keep).collect();
+        edits[index] = Edit::Replace(val.into());
+        self.rebuild(edits);
+        true
+    }
+
+    /// Change the level of the record
+    ///
+    /// The `level` field appended by `LevelPlugin` is updated as well, so the targets see the same
+    /// level in all formats.
+    ///
+    /// ```
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.mount(logkit::LevelPlugin);
+    ///
+    /// if let Some(mut record) = logger.spawn(logkit::LEVEL_INFO, logkit::source!()) {
+    ///     record.append("status", &503);
+    ///     record.set_level(logkit::LEVEL_ERROR);
+    ///     record.finish();
+    ///     assert_eq!(record.level(), logkit::LEVEL_ERROR);
+    ///     assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"level\":\"error\",\"status\":503}\n");
+    /// }
+    /// ```
+    pub fn set_level(&mut self, level: Level) -> &mut Self {
+        self.level = level;
+
+        let Some(index) = self.fields.iter().zip(self.fields()).position(|(span, field)| span.plugin && field.key() == "level") else {
+            return self;
+        };
+
+        let text = match level_to_str(level) {
+            Some(text) => Value::Str(text.into()),
+            None => Value::Str(level.to_string().into()),
+        };
+
+        let mut edits: Vec<Edit> = self.fields.iter().map(|_| Edit::Keep).collect();
+        edits[index] = Edit::Replace(text);
+        self.rebuild(edits);
+        self
+    }
+
+    // rewrite the buffer with the edited fields, keeping the record finished if it was
+    //
+    // The fields are written into the render cache, which then swaps with the buffer. Metrics and
+    // dimensions follow their fields when they are renamed, and are removed with them.
+    fn rebuild(&mut self, edits: Vec<Edit>) {
+        let finished = self.buffer.ends_with(b"\n");
+        let mut taken: Vec<Cow<str>> = match edits.iter().any(|edit| matches!(edit, Edit::Rename)) {
+            true => self.fields.iter().zip(&edits).filter(|(_, edit)| !matches!(edit, Edit::Drop | Edit::Rename)).map(|(span, _)| span.name(&self.buffer)).collect(),
+            false => vec![],
+        };
+        let mut renamed: Vec<(String, String)> = vec![];
+        let mut buffer = std::mem::take(&mut self.cache);
+        let mut count = 0;
+
+        buffer.clear();
+        buffer.push(b'{');
+
+        for (index, edit) in edits.into_iter().enumerate() {
+            let span = self.fields[index];
+            let beg = buffer.len();
+            let mut typed = span.typed;
+
+            let value = match edit {
+                Edit::Drop => continue,
+                Edit::Keep => None,
+                Edit::Replace(mut value) => {
+                    value.validate();
+                    Some(value)
+                }
+                Edit::Rename => {
+                    let old = span.name(&self.buffer);
+                    let name = (2..).map(|n| format!("{}_{}", old, n)).find(|val| !taken.iter().any(|key| key == val)).unwrap_or_default();
+                    name.as_str().encode(&mut buffer);
+                    buffer.push(b':');
+                    renamed.push((old.into_owned(), name.clone()));
+                    taken.push(name.into());
+                    None
+                }
+            };
+
+            if buffer.len() == beg {
+                buffer.extend_from_slice(&self.buffer[span.key..span.val]);
+            }
+
+            let mid = buffer.len();
+
+            match (value, span.typed.and_then(|index| self.values.get(index))) {
+                (Some(value), _) => {
+                    value.encode(&mut buffer);
+                    typed = Some(self.values.len());
+                    self.values.push(value);
+                }
+                (None, Some(value)) if span.val == span.end => value.encode(&mut buffer),
+                (None, _) => buffer.extend_from_slice(&self.buffer[span.val..span.end]),
+            }
+
+            self.fields[count] = Span {key: beg, val: mid, end: buffer.len(), plugin: span.plugin, typed};
+            count += 1;
+            buffer.push(b',');
+        }
+
+        if finished {
+            match buffer.last_mut() {
+                Some(val) if *val == b',' => *val = b'}',
+                _ => buffer.push(b'}'),
+            }
+
+            buffer.push(b'\n');
+        }
+
+        drop(taken);
+        self.fields.truncate(count);
+        self.cache = std::mem::replace(&mut self.buffer, buffer);
+        self.nested.clear();
+
+        if self.metrics.is_empty() && self.dimensions.is_empty() {
+            return;
+        }
+
+        // a mark stays with the caller's field of its key, or moves to the first renamed one
+        let follow = |name: &str| -> Option<String> {
+            let kept = |plugin: bool| self.fields.iter().any(|span| span.plugin == plugin && span.name(&self.buffer) == name);
+
+            match renamed.iter().find(|(old, _)| old == name) {
+                _ if kept(false) => Some(name.to_string()),
+                Some((_, new)) => Some(new.clone()),
+                None if kept(true) => Some(name.to_string()),
+                None => None,
+            }
+        };
+
+        let mut metrics = std::mem::take(&mut self.metrics);
+        let mut dimensions = std::mem::take(&mut self.dimensions);
+
+        metrics.retain_mut(|metric| follow(&metric.key).map(|key| metric.key = key).is_some());
+        dimensions.retain_mut(|dimension| follow(dimension).map(|key| *dimension = key).is_some());
+
+        self.metrics = metrics;
+        self.dimensions = dimensions;
+    }
+
+    // mark the fields appended from now on as the fields of plugins
+    #[inline]
+    pub(crate) fn by_plugin(&mut self, plugin: bool) {
+        self.plugin = plugin;
+    }
+
     /// Mark the end of the record
     ///
     /// ```
@@ -89,6 +623,8 @@ impl Record {
     /// ```
     #[inline]
     pub fn finish(&mut self) {
+        self.encode_values();
+
         match self.buffer.last_mut() {
             Some(val) if *val == b',' => *val = b'}',
             _ => self.buffer.push(b'}'),
@@ -97,6 +633,37 @@ impl Record {
         self.buffer.push(b'\n');
     }
 
+    // encode the pending typed values into the buffer
+    fn encode_values(&mut self) {
+        if !self.fields.iter().any(|span| span.typed.is_some() && span.val == span.end) {
+            return;
+        }
+
+        // build into the render cache, then keep the old buffer as the cache
+        let mut buffer = std::mem::take(&mut self.cache);
+        buffer.clear();
+        let mut prev = 0;
+
+        for span in &mut self.fields {
+            buffer.extend_from_slice(&self.buffer[prev..span.key]);
+            let key = buffer.len();
+            buffer.extend_from_slice(&self.buffer[span.key..span.val]);
+            let val = buffer.len();
+
+            match span.typed.and_then(|index| self.values.get(index)) {
+                Some(value) if span.val == span.end => value.encode(&mut buffer),
+                _ => buffer.extend_from_slice(&self.buffer[span.val..span.end]),
+            }
+
+            prev = span.end;
+            *span = Span {key, val, end: buffer.len(), ..*span};
+        }
+
+        buffer.extend_from_slice(&self.buffer[prev..]);
+        self.cache = std::mem::replace(&mut self.buffer, buffer);
+        self.nested.clear();
+    }
+
     /// Get the final buffer
     ///
     /// ```
@@ -109,4 +676,361 @@ impl Record {
     pub fn buffer(&self) -> &Vec<u8> {
         &self.buffer
     }
+
+    /// Render the finished record with a format
+    ///
+    /// The output is kept in a cache owned by the record, which is reused along with it.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append("msg", &"less is more");
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.render(&logkit::JsonFormat)), "{\"msg\":\"less is more\"}\n");
+    /// ```
+    #[inline]
+    pub fn render(&mut self, format: &dyn Format) -> &[u8] {
+        let mut cache = std::mem::take(&mut self.cache);
+        cache.clear();
+        format.format(self, &mut cache);
+        self.cache = cache;
+        &self.cache
+    }
+}
+
+/// Fields encoded in advance
+///
+/// Constant fields like the service name are encoded once, then copied into records as bytes.
+/// Use the `fields!` macro to create them.
+///
+/// ```
+/// let mut fields = logkit::Fields::new();
+/// fields.append("pid", &12345);
+/// assert_eq!(fields.len(), 1);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Fields {
+    buffer: Vec<u8>, // fields followed by commas
+    spans: Vec<Span>,
+    nested: Vec<(usize, usize)>, // key and '{' of each object opened by the last append_nested
+}
+
+impl Fields {
+    /// Create an empty set of fields
+    #[inline]
+    pub const fn new() -> Self {
+        Self {buffer: vec![], spans: vec![], nested: vec![]}
+    }
+
+    /// Append field's key and value
+    #[inline]
+    pub fn append(&mut self, key: &str, val: &impl Encode) -> &mut Self {
+        self.nested.clear();
+        let beg = self.buffer.len();
+        key.encode(&mut self.buffer);
+        self.buffer.push(b':');
+        let mid = self.buffer.len();
+        val.encode(&mut self.buffer);
+        self.spans.push(Span {key: beg, val: mid, end: self.buffer.len(), plugin: false, typed: None});
+        self.buffer.push(b',');
+        self
+    }
+
+    /// Append a field nested in objects along the path, see `Record::append_nested`
+    ///
+    /// ```
+    /// let fields = logkit::fields!(service.name = "checkout", env = "prod", service.version = "1.2.0");
+    ///
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.extend(&fields);
+    /// record.finish();
+    /// assert_eq!(String::from_utf8_lossy(record.buffer().as_slice()), "{\"service\":{\"name\":\"checkout\",\"version\":\"1.2.0\"},\"env\":\"prod\"}\n");
+    /// ```
+    #[inline]
+    pub fn append_nested(&mut self, path: &[&str], val: &impl Encode) -> &mut Self {
+        append_nested(&mut self.buffer, &mut self.spans, &mut self.nested, false, path, val);
+        self
+    }
+
+    /// Append all fields of another set
+    #[inline]
+    pub fn extend(&mut self, fields: &Fields) -> &mut Self {
+        self.nested.clear();
+        let base = self.buffer.len();
+        self.buffer.extend_from_slice(&fields.buffer);
+        self.spans.extend(fields.spans.iter().map(|span| Span {key: base + span.key, val: base + span.val, end: base + span.end, ..*span}));
+        self
+    }
+
+    /// Number of fields
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Check if there are no fields
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+// append a field nested in objects along the path, shared by Record and Fields
+fn append_nested(buffer: &mut Vec<u8>, spans: &mut Vec<Span>, nested: &mut Vec<(usize, usize)>, plugin: bool, path: &[&str], val: &impl Encode) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let reuse = nested.iter().zip(parents).take_while(|((key, brace), name)| {
+        decode_str(&buffer[*key..*brace - 1]).is_some_and(|key| key == **name)
+    }).count();
+
+    if reuse > 0 {
+        // reopen the shared objects, keeping the deeper ones closed
+        buffer.truncate(buffer.len() - 1 - reuse);
+        buffer.push(b',');
+        spans.pop();
+    } else if !parents.is_empty() && merge_nested(buffer, spans, plugin, parents, last, val) {
+        nested.clear();
+        return;
+    }
+
+    nested.truncate(reuse);
+
+    let beg = buffer.len();
+
+    for name in &parents[reuse..] {
+        let beg = buffer.len();
+        let mid = Group::open(buffer, name);
+        nested.push((beg, mid));
+    }
+
+    last.encode(buffer);
+    buffer.push(b':');
+    let mid = buffer.len();
+    val.encode(buffer);
+    buffer.resize(buffer.len() + nested.len(), b'}');
+
+    match nested.first() {
+        Some((beg, mid)) => spans.push(Span {key: *beg, val: *mid, end: buffer.len(), plugin, typed: None}),
+        None => spans.push(Span {key: beg, val: mid, end: buffer.len(), plugin, typed: None}),
+    }
+
+    buffer.push(b',');
+}
+
+// insert a field into an earlier object with the same leading key, returns false if there is none
+fn merge_nested(buffer: &mut Vec<u8>, spans: &mut [Span], plugin: bool, parents: &[&str], last: &str, val: &impl Encode) -> bool {
+    let found = spans.iter().rposition(|span| {
+        span.plugin == plugin && span.typed.is_none() && buffer.get(span.val) == Some(&b'{') && decode_str(&buffer[span.key..span.val - 1]).is_some_and(|key| key == parents[0])
+    });
+
+    let Some(index) = found else {
+        return false;
+    };
+
+    let span = spans[index];
+    let Some(mut value) = Value::from_json(&buffer[span.val..span.end]).map(Value::into_owned) else {
+        return false;
+    };
+
+    let mut node = &mut value;
+
+    for name in &parents[1..] {
+        let Value::Object(items) = node else {
+            return false;
+        };
+
+        let index = match items.iter().rposition(|(key, item)| key == name && matches!(item, Value::Object(_))) {
+            Some(index) => index,
+            None => {
+                items.push((Cow::Owned(name.to_string()), Value::Object(vec![])));
+                items.len() - 1
+            }
+        };
+
+        node = &mut items[index].1;
+    }
+
+    let Value::Object(items) = node else {
+        return false;
+    };
+
+    let mut json = vec![];
+    val.encode(&mut json);
+    items.push((Cow::Owned(last.to_string()), Value::Raw(Cow::Owned(String::from_utf8_lossy(&json).into_owned()))));
+
+    json.clear();
+    value.encode(&mut json);
+
+    // shift the fields after the merged one
+    let (old, new) = (span.end - span.val, json.len());
+    buffer.splice(span.val..span.end, json);
+    spans[index].end = span.val + new;
+
+    for span in &mut spans[index + 1..] {
+        *span = Span {key: span.key - old + new, val: span.val - old + new, end: span.end - old + new, ..*span};
+    }
+
+    true
+}
+
+// how rebuild treats a field
+enum Edit {
+    Keep,
+    Drop,
+    Rename,
+    Replace(Value<'static>),
+}
+
+/// How to resolve fields with duplicate keys
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// keep all fields
+    #[default]
+    KeepAll,
+
+    /// keep the first field
+    FirstWins,
+
+    /// keep the last field
+    LastWins,
+
+    /// rename the later fields with a suffix
+    Rename,
+}
+
+/// Nested object under construction
+///
+/// Created by `Record::group`, the appended fields are written into the record directly.
+#[derive(Debug)]
+pub struct Group<'a> {
+    buffer: &'a mut Vec<u8>,
+}
+
+impl<'a> Group<'a> {
+    /// Append field's key and value to the object
+    #[inline]
+    pub fn append(&mut self, key: &str, val: &impl Encode) -> &mut Self {
+        key.encode(self.buffer);
+        self.buffer.push(b':');
+        val.encode(self.buffer);
+        self.buffer.push(b',');
+        self
+    }
+
+    /// Append a nested object built by a closure
+    #[inline]
+    pub fn group(&mut self, key: &str, build: impl FnOnce(&mut Group)) -> &mut Self {
+        Group::open(self.buffer, key);
+        build(&mut Group {buffer: self.buffer});
+        Group::close(self.buffer);
+        self.buffer.push(b',');
+        self
+    }
+
+    // write the key and '{', return the position of '{'
+    #[inline]
+    fn open(buffer: &mut Vec<u8>, key: &str) -> usize {
+        key.encode(buffer);
+        buffer.push(b':');
+        buffer.push(b'{');
+        buffer.len() - 1
+    }
+
+    #[inline]
+    fn close(buffer: &mut Vec<u8>) {
+        match buffer.last_mut() {
+            Some(val) if *val == b',' => *val = b'}',
+            _ => buffer.push(b'}'),
+        }
+    }
+}
+
+/// Numeric types accepted by `Record::metric`
+///
+/// This trait is sealed, CloudWatch rejects metrics which are not numbers.
+///
+/// ```compile_fail
+/// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+/// record.metric("latency", &"12.5", "Milliseconds");
+/// ```
+pub trait Numeric: Encode + numeric::Sealed {}
+
+mod numeric {
+    pub trait Sealed {}
+}
+
+macro_rules! numeric_impl {
+    ($($ty:ty),+) => {$(
+        impl numeric::Sealed for $ty {}
+        impl Numeric for $ty {}
+    )+};
+}
+
+numeric_impl!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// Metric mark of a field
+#[derive(Debug, Clone)]
+pub struct Metric {
+    /// field key
+    pub key: String,
+
+    /// unit, like Seconds, Milliseconds, Bytes or Count
+    pub unit: &'static str,
+}
+
+/// Field of a record
+///
+/// The key and value stay in their encoded JSON form until they are requested, typed values are
+/// read as they are.
+#[derive(Debug, Clone, Copy)]
+pub struct Field<'a> {
+    key: &'a [u8],
+    val: &'a [u8],
+    typed: Option<&'a Value<'static>>,
+}
+
+impl<'a> Field<'a> {
+    /// Field's key
+    #[inline]
+    pub fn key(&self) -> Cow<'a, str> {
+        decode_str(self.key).unwrap_or_else(|| String::from_utf8_lossy(self.key))
+    }
+
+    /// Field's value in JSON
+    ///
+    /// Typed values are encoded on demand until the record is finished.
+    #[inline]
+    pub fn json(&self) -> Cow<'a, [u8]> {
+        match self.typed {
+            Some(value) if self.val.is_empty() => {
+                let mut buf = vec![];
+                value.encode(&mut buf);
+                Cow::Owned(buf)
+            }
+            _ => Cow::Borrowed(self.val),
+        }
+    }
+
+    /// Field's structured value
+    ///
+    /// Typed values are borrowed, others are parsed from JSON. A custom `Encode` impl that doesn't
+    /// produce valid JSON is treated as a string.
+    ///
+    /// ```
+    /// let mut record = logkit::Record::new(logkit::LEVEL_TRACE, logkit::source!());
+    /// record.append_value("ports", vec![logkit::Value::from(80), logkit::Value::from(443)]);
+    /// record.finish();
+    ///
+    /// let field = record.fields().next().unwrap();
+    /// assert!(matches!(field.value(), std::borrow::Cow::Borrowed(logkit::Value::Array(_))));
+    /// ```
+    #[inline]
+    pub fn value(&self) -> Cow<'a, Value<'a>> {
+        if let Some(value) = self.typed {
+            return value.parsed();
+        }
+
+        Cow::Owned(Value::from_json(self.val).unwrap_or_else(|| Value::Str(String::from_utf8_lossy(self.val))))
+    }
 }
\ No newline at end of file
@@ -1,8 +1,17 @@
 //! Source represent source info
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Source Info
 ///
 /// Save the source info when the user calls the macros.
+///
+/// ```
+/// let source = logkit::Source::new("src/net.rs", 9, 5).with_module("app::net").with_function("serve");
+/// assert_eq!(source.file, "src/net.rs");
+/// assert_eq!(source.module(), "app::net");
+/// assert_eq!(source.function(), "serve");
+/// assert!(source.callsite().is_none());
+/// ```
 #[derive(Debug, Default, Clone)]
 pub struct Source {
     /// File name in which it was invoked
@@ -13,19 +22,170 @@ pub struct Source {
 
     /// Column number at which it was invoked
     pub column: u32,
+
+    module: &'static str,                // module path
+    function: &'static str,              // type name of an item inside the function, stripped when read
+    callsite: Option<&'static Callsite>, // level cache of the call site
+}
+
+impl Source {
+    /// Create a source info without module and function
+    #[inline]
+    pub const fn new(file: &'static str, line: u32, column: u32) -> Self {
+        Self {file, line, column, module: "", function: "", callsite: None}
+    }
+
+    /// Set the module path
+    #[inline]
+    pub const fn with_module(mut self, module: &'static str) -> Self {
+        self.module = module;
+        self
+    }
+
+    /// Set the function name
+    ///
+    /// The macros save the type name of an item defined inside the function, it's stripped only
+    /// when it's read by `function()`, so disabled logs don't pay for it.
+    #[inline]
+    pub const fn with_function(mut self, function: &'static str) -> Self {
+        self.function = function;
+        self
+    }
+
+    /// Set the cache of the level decision for the call site
+    #[inline]
+    pub const fn with_callsite(mut self, callsite: &'static Callsite) -> Self {
+        self.callsite = Some(callsite);
+        self
+    }
+
+    /// Module path in which it was invoked
+    #[inline]
+    pub fn module(&self) -> &'static str {
+        self.module
+    }
+
+    /// Cache of the level decision for the place in which it was invoked
+    #[inline]
+    pub fn callsite(&self) -> Option<&'static Callsite> {
+        self.callsite
+    }
+
+    /// Name of the crate in which it was invoked
+    ///
+    /// ```
+    /// let source = logkit::Source::default().with_module("app::net::http");
+    /// assert_eq!(source.crate_name(), "app");
+    /// ```
+    #[inline]
+    pub fn crate_name(&self) -> &'static str {
+        match self.module.split_once("::") {
+            Some((name, _)) => name,
+            None => self.module,
+        }
+    }
+
+    /// Function in which it was invoked, with its path inside the module like `Type::method`
+    ///
+    /// ```
+    /// let source = logkit::Source::default().with_module("app::net").with_function("app::net::Server::serve::f");
+    /// assert_eq!(source.function(), "Server::serve");
+    ///
+    /// let source = logkit::Source::default().with_module("app::net").with_function("Server::serve");
+    /// assert_eq!(source.function(), "Server::serve");
+    /// ```
+    pub fn function(&self) -> &'static str {
+        let mut name = match self.function.strip_suffix("::f") {
+            Some(val) => val,
+            None => return self.function,
+        };
+
+        while let Some(val) = name.strip_suffix("::{{closure}}") {
+            name = val;
+        }
+
+        match name.strip_prefix(self.module).and_then(|val| val.strip_prefix("::")) {
+            Some(val) => val,
+            None => name.rsplit("::").next().unwrap_or(name),
+        }
+    }
 }
 
 /// Create a new source info
 ///
 /// ```
+/// fn serve() -> logkit::Source {
+///     logkit::source!()
+/// }
+///
 /// let mut source = logkit::source!();
 /// assert!(source.file == "src/source.rs" || source.file == "src\\source.rs");
 /// assert!(source.line > 0);
 /// assert!(source.column > 0);
+/// assert_eq!(source.module(), module_path!());
+/// assert_eq!(source.crate_name(), module_path!().split("::").next().unwrap());
+/// assert!(serve().function().ends_with("serve"));
 /// ```
 #[macro_export]
 macro_rules! source {
     () => {{
-        $crate::Source { file: file!(), line: line!(), column: column!() }
+        fn f() {}
+        static CALLSITE: $crate::Callsite = $crate::Callsite::new();
+
+        $crate::Source::new(file!(), line!(), column!())
+            .with_module(module_path!())
+            .with_function($crate::source::source_function(f))
+            .with_callsite(&CALLSITE)
     }};
-}
\ No newline at end of file
+}
+
+/// Callsite Cache
+///
+/// Each `source!()` owns a static callsite, which remembers the level directive that matched it
+/// for the last logger used there, so disabled logs skip matching the directives.
+///
+/// The cache is keyed by a generation that is unique to a logger and its directives, a logger gets
+/// a new one whenever its limit or directives change. Loggers sharing a call site never read each
+/// other's results, they only replace them. Generations have 48 bits, they wrap only after 2^48
+/// changes.
+#[derive(Debug, Default)]
+pub struct Callsite {
+    cache: AtomicU64, // generation in high 48 bits, index of the directive plus 1 in low 16 bits
+}
+
+impl Callsite {
+    /// Create an empty cache
+    pub const fn new() -> Self {
+        Self {cache: AtomicU64::new(0)}
+    }
+
+    // get the cached directive, Some(None) if no directive matched, generation 0 is never cached
+    #[inline]
+    pub(crate) fn get(&self, generation: u64) -> Option<Option<usize>> {
+        let cache = self.cache.load(Ordering::Relaxed);
+
+        match generation != 0 && cache >> 16 == generation {
+            true => Some((cache & 0xffff).checked_sub(1).map(|index| index as usize)),
+            false => None,
+        }
+    }
+
+    // save the directive resolved by a generation, directives beyond 16 bits are not cached
+    #[inline]
+    pub(crate) fn set(&self, generation: u64, directive: Option<usize>) {
+        let slot = match directive {
+            Some(index) if index < 0xffff => index as u64 + 1,
+            Some(_) => return,
+            None => 0,
+        };
+
+        self.cache.store((generation << 16) | slot, Ordering::Relaxed);
+    }
+}
+
+/// Type name of a function defined inside the enclosing function
+#[doc(hidden)]
+#[inline]
+pub fn source_function<T>(_: T) -> &'static str {
+    std::any::type_name::<T>()
+}
@@ -1,5 +1,6 @@
 //! Target trait and built-in output targets
 use super::define::*;
+use super::format::*;
 
 /// The Target Trait
 ///
@@ -8,6 +9,36 @@ use super::define::*;
 pub trait Target: AnyTarget + Send + Sync + 'static {
     /// Write logs from buf to target
     fn write(&self, buf: &[u8]);
+
+    /// The format of logs written to target, JSON if `None`
+    #[inline]
+    fn format(&self) -> Option<&dyn Format> {
+        None
+    }
+
+    /// Wrap the target to output logs in another format
+    ///
+    /// ```
+    /// use logkit::Target;
+    ///
+    /// let mut logger = logkit::Logger::new(None);
+    /// logger.route(logkit::StdoutTarget.with_format(logkit::JsonFormat));
+    /// logkit::set_default_logger(logger);
+    /// ```
+    fn with_format(self, format: impl Format) -> FormatTarget<Self> where Self: Sized {
+        let mut header = vec![];
+        format.header(&mut header);
+
+        if !header.is_empty() {
+            self.header(&header);
+        }
+
+        FormatTarget {target: self, format: Box::new(format)}
+    }
+
+    /// Write the header of a format if the output is new, like an empty file
+    #[inline]
+    fn header(&self, _buf: &[u8]) {}
 }
 
 /// Any Support
@@ -23,6 +54,39 @@ impl<T: Any> AnyTarget for T {
     }
 }
 
+/// Write to a target in a custom format
+///
+/// ```
+/// use logkit::Target;
+///
+/// let target = logkit::StderrTarget.with_format(logkit::JsonFormat);
+/// assert!(target.format().is_some());
+/// ```
+pub struct FormatTarget<T: Target> {
+    /// inner target
+    pub target: T,
+
+    /// output format
+    pub format: Box<dyn Format>,
+}
+
+impl<T: Target> Target for FormatTarget<T> {
+    #[inline]
+    fn write(&self, buf: &[u8]) {
+        self.target.write(buf);
+    }
+
+    #[inline]
+    fn format(&self) -> Option<&dyn Format> {
+        Some(self.format.as_ref())
+    }
+
+    #[inline]
+    fn header(&self, buf: &[u8]) {
+        self.target.header(buf);
+    }
+}
+
 /// Write to stdout
 ///
 /// ```
@@ -53,6 +117,65 @@ impl Target for StderrTarget {
     }
 }
 
+/// Write human-readable text to the console
+///
+/// Records are rendered by TextFormat, with colors if the stream is a terminal. Colors can be
+/// turned off by setting `NO_COLOR`, or forced on by setting `CLICOLOR_FORCE` to non-zero.
+///
+/// ```
+/// let mut logger = logkit::Logger::new(None);
+/// logger.route(logkit::ConsoleTarget::stderr());
+/// logkit::set_default_logger(logger);
+/// ```
+pub struct ConsoleTarget {
+    /// write to stderr instead of stdout
+    pub stderr: bool,
+
+    /// output format
+    pub format: TextFormat,
+}
+
+impl ConsoleTarget {
+    /// Write to stdout
+    pub fn stdout() -> Self {
+        use std::io::IsTerminal;
+        Self {stderr: false, format: TextFormat::new(console_color(std::io::stdout().is_terminal()))}
+    }
+
+    /// Write to stderr
+    pub fn stderr() -> Self {
+        use std::io::IsTerminal;
+        Self {stderr: true, format: TextFormat::new(console_color(std::io::stderr().is_terminal()))}
+    }
+}
+
+impl Target for ConsoleTarget {
+    #[inline]
+    fn write(&self, buf: &[u8]) {
+        let _ = match self.stderr {
+            true => std::io::stderr().write_all(buf),
+            false => std::io::stdout().write_all(buf),
+        };
+    }
+
+    #[inline]
+    fn format(&self) -> Option<&dyn Format> {
+        Some(&self.format)
+    }
+}
+
+fn console_color(tty: bool) -> bool {
+    if std::env::var_os("NO_COLOR").is_some_and(|val| !val.is_empty()) {
+        return false;
+    }
+
+    if std::env::var_os("CLICOLOR_FORCE").is_some_and(|val| !val.is_empty() && val != "0") {
+        return true;
+    }
+
+    tty
+}
+
 /// Write to a file
 ///
 /// ```
@@ -94,4 +217,287 @@ impl Target for FileTarget {
             Err(err) => { eprintln!("Error: {}", err); }
         };
     }
-}
\ No newline at end of file
+
+    fn header(&self, buf: &[u8]) {
+        match self.file.lock() {
+            Ok(mut obj) => match obj.metadata() {
+                Ok(meta) if meta.len() > 0 => {}
+                Ok(_) => match obj.write_all(buf) {
+                    Ok(_) => {}
+                    Err(err) => { eprintln!("Error: {}", err); }
+                }
+                Err(err) => { eprintln!("Error: {}", err); }
+            }
+            Err(err) => { eprintln!("Error: {}", err); }
+        };
+    }
+}
+
+const MMAP_MAGIC: &[u8; 8] = b"LOGKITMM";
+const MMAP_HEADER: usize = 64; // magic, capacity, head and tail, padded for future use
+const MMAP_CAPACITY: usize = 8;
+const MMAP_HEAD: usize = 16;
+const MMAP_TAIL: usize = 24;
+const MMAP_LIMIT: u64 = 1 << 62; // bound of head, far from overflowing
+
+/// Write to a memory-mapped ring buffer
+///
+/// Records are stored as length-prefixed frames in a circular buffer inside a memory-mapped file.
+/// The header records the write position, and the mapped pages are owned by the kernel, so every
+/// finished record survives a segfault or a SIGKILL of the process. Use `MmapReader` to extract
+/// them afterwards. When the buffer is full, the oldest records are overwritten.
+///
+/// ```
+/// fn main() -> anyhow::Result<()> {
+///     let mut sample = std::env::temp_dir();
+///     sample.push("sample_target.mmap");
+///     let _ = std::fs::remove_file(&sample);
+///
+///     let mut logger = logkit::Logger::new(None);
+///     logger.route(logkit::MmapTarget::new(&sample, 1024 * 1024)?);
+///     logkit::set_default_logger(logger);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MmapTarget {
+    /// mapped file
+    pub ring: Mutex<memmap2::MmapMut>,
+}
+
+impl MmapTarget {
+    /// Create a MmapTarget with a path and the capacity of the ring in bytes
+    ///
+    /// An existing file with the same capacity is reused, and the records in it are preserved.
+    /// The file is reset if its header is damaged.
+    ///
+    /// ```
+    /// use logkit::Target;
+    ///
+    /// fn main() -> anyhow::Result<()> {
+    ///     let mut sample = std::env::temp_dir();
+    ///     sample.push("sample_damaged.mmap");
+    ///     let _ = std::fs::remove_file(&sample);
+    ///
+    ///     logkit::MmapTarget::new(&sample, 32)?.write(b"first\n");
+    ///
+    ///     let mut bytes = std::fs::read(&sample)?;
+    ///     bytes[24..32].copy_from_slice(&u64::MAX.to_le_bytes()); // tail beyond head
+    ///     std::fs::write(&sample, bytes)?;
+    ///
+    ///     logkit::MmapTarget::new(&sample, 32)?.write(b"second\n");
+    ///     assert_eq!(logkit::MmapReader::open(&sample)?.records(), vec![b"second\n".to_vec()]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new(path: impl AsRef<Path>, capacity: u64) -> anyhow::Result<Self> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        if capacity == 0 {
+            anyhow::bail!("mmap capacity must be greater than zero");
+        }
+
+        let file = std::fs::OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path)?;
+        let size = MMAP_HEADER as u64 + capacity;
+        let keep = file.metadata()?.len() == size;
+
+        if !keep {
+            file.set_len(size)?;
+        }
+
+        let mut ring = unsafe { memmap2::MmapMut::map_mut(&file)? };
+
+        if !keep || &ring[..MMAP_MAGIC.len()] != MMAP_MAGIC || mmap_get(&ring, MMAP_CAPACITY) != capacity || !mmap_valid(&ring) {
+            ring[..MMAP_HEADER].fill(0);
+            ring[..MMAP_MAGIC.len()].copy_from_slice(MMAP_MAGIC);
+            mmap_set(&mut ring, MMAP_CAPACITY, capacity);
+        }
+
+        Ok(Self {ring: Mutex::new(ring)})
+    }
+
+    /// Flush the mapped pages to disk
+    ///
+    /// Not required to survive a process crash, only to survive a crash of the operating system.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        match self.ring.lock() {
+            Ok(obj) => Ok(obj.flush()?),
+            Err(err) => anyhow::bail!("{}", err),
+        }
+    }
+}
+
+impl Target for MmapTarget {
+    fn write(&self, buf: &[u8]) {
+        let mut ring = match self.ring.lock() {
+            Ok(obj) => obj,
+            Err(err) => { eprintln!("Error: {}", err); return; }
+        };
+
+        let capacity = mmap_get(&ring, MMAP_CAPACITY);
+        let length = match u32::try_from(buf.len()) {
+            Ok(val) if val as u64 + 4 <= capacity => val,
+            _ => { eprintln!("Error: record is larger than the mmap capacity"); return; }
+        };
+
+        if !mmap_valid(&ring) {
+            mmap_set(&mut ring, MMAP_HEAD, 0); // corrupted header, reset the ring
+            mmap_set(&mut ring, MMAP_TAIL, 0);
+        }
+
+        let head = mmap_get(&ring, MMAP_HEAD);
+        let mut tail = mmap_get(&ring, MMAP_TAIL);
+        let (body, end) = match head.checked_add(4).and_then(|body| Some((body, body.checked_add(length as u64)?))) {
+            Some(val) => val,
+            None => { eprintln!("Error: mmap position overflow"); return; }
+        };
+
+        // drop the oldest frames until the new one fits
+        while end - tail > capacity {
+            let mut prefix = [0u8; 4];
+            mmap_read(&ring, tail, &mut prefix);
+
+            tail = match tail.checked_add(4 + u32::from_le_bytes(prefix) as u64) {
+                Some(val) if val <= head => val,
+                _ => head, // corrupted frame, drop all old frames
+            };
+        }
+
+        // publish the tail before overwriting old frames, and the head after the new frame is
+        // complete, so that a crash at any point leaves only whole frames between them
+        mmap_set(&mut ring, MMAP_TAIL, tail);
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+
+        mmap_write(&mut ring, head, &length.to_le_bytes());
+        mmap_write(&mut ring, body, buf);
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+
+        mmap_set(&mut ring, MMAP_HEAD, end);
+    }
+}
+
+/// Read records back from a MmapTarget's file
+///
+/// ```
+/// use logkit::Target;
+///
+/// fn main() -> anyhow::Result<()> {
+///     let mut sample = std::env::temp_dir();
+///     sample.push("sample_reader.mmap");
+///     let _ = std::fs::remove_file(&sample);
+///
+///     let target = logkit::MmapTarget::new(&sample, 32)?;
+///     target.write(b"first\n");
+///     target.write(b"second\n");
+///     target.write(b"third\n");
+///     target.write(b"fourth\n"); // the first record is overwritten
+///
+///     let reader = logkit::MmapReader::open(&sample)?;
+///     assert_eq!(reader.records(), vec![b"second\n".to_vec(), b"third\n".to_vec(), b"fourth\n".to_vec()]);
+///
+///     // a damaged header is ignored by the reader and reset by the next write
+///     if let Ok(mut ring) = target.ring.lock() {
+///         ring[16..24].copy_from_slice(&(u64::MAX - 2).to_le_bytes()); // head
+///         ring[24..32].copy_from_slice(&(u64::MAX - 2).to_le_bytes()); // tail
+///     }
+///
+///     assert!(reader.records().is_empty());
+///     target.write(b"fifth\n");
+///     assert_eq!(reader.records(), vec![b"fifth\n".to_vec()]);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MmapReader {
+    /// mapped file
+    pub ring: memmap2::Mmap,
+}
+
+impl MmapReader {
+    /// Open a file written by MmapTarget
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let ring = unsafe { memmap2::Mmap::map(&file)? };
+
+        if ring.len() < MMAP_HEADER || &ring[..MMAP_MAGIC.len()] != MMAP_MAGIC {
+            anyhow::bail!("not a logkit mmap file");
+        }
+
+        if mmap_get(&ring, MMAP_CAPACITY) != (ring.len() - MMAP_HEADER) as u64 {
+            anyhow::bail!("mmap capacity mismatch");
+        }
+
+        Ok(Self {ring})
+    }
+
+    /// Extract all records in the order they were written
+    pub fn records(&self) -> Vec<Vec<u8>> {
+        let head = mmap_get(&self.ring, MMAP_HEAD);
+        let mut tail = mmap_get(&self.ring, MMAP_TAIL);
+        let mut records = vec![];
+
+        if !mmap_valid(&self.ring) {
+            return records; // corrupted header
+        }
+
+        while let Some(body) = tail.checked_add(4).filter(|body| *body <= head) {
+            let mut prefix = [0u8; 4];
+            mmap_read(&self.ring, tail, &mut prefix);
+
+            let length = u32::from_le_bytes(prefix) as u64;
+            let end = match body.checked_add(length) {
+                Some(end) if end <= head => end,
+                _ => break, // corrupted frame
+            };
+
+            let mut record = vec![0u8; length as usize];
+            mmap_read(&self.ring, body, &mut record);
+            records.push(record);
+
+            tail = end;
+        }
+
+        records
+    }
+}
+
+#[inline]
+fn mmap_valid(ring: &[u8]) -> bool {
+    let head = mmap_get(ring, MMAP_HEAD);
+    let tail = mmap_get(ring, MMAP_TAIL);
+    head <= MMAP_LIMIT && tail <= head && head - tail <= mmap_get(ring, MMAP_CAPACITY)
+}
+
+#[inline]
+fn mmap_get(ring: &[u8], offset: usize) -> u64 {
+    let mut val = [0u8; 8];
+    val.copy_from_slice(&ring[offset..offset + 8]);
+    u64::from_le_bytes(val)
+}
+
+#[inline]
+fn mmap_set(ring: &mut [u8], offset: usize, val: u64) {
+    ring[offset..offset + 8].copy_from_slice(&val.to_le_bytes());
+}
+
+#[inline]
+fn mmap_read(ring: &[u8], pos: u64, out: &mut [u8]) {
+    let data = &ring[MMAP_HEADER..];
+    let start = (pos % data.len() as u64) as usize;
+    let first = out.len().min(data.len() - start);
+    let (head, rest) = out.split_at_mut(first);
+    head.copy_from_slice(&data[start..start + first]);
+    rest.copy_from_slice(&data[..rest.len()]);
+}
+
+#[inline]
+fn mmap_write(ring: &mut [u8], pos: u64, buf: &[u8]) {
+    let data = &mut ring[MMAP_HEADER..];
+    let start = (pos % data.len() as u64) as usize;
+    let first = buf.len().min(data.len() - start);
+    data[start..start + first].copy_from_slice(&buf[..first]);
+    data[..buf.len() - first].copy_from_slice(&buf[first..]);
+}
@@ -0,0 +1,623 @@
+//! Structured value of a record's field
+use super::define::*;
+use std::borrow::Cow;
+
+/// Field Value
+///
+/// Fields appended with `Record::append` are encoded to JSON at once, this type gives formats a
+/// structured view of them. Strings and keys borrow from the record whenever no unescaping is
+/// needed. Owned values appended with `Record::append_value` are kept until the record is
+/// finished.
+///
+/// ```
+/// let val = logkit::Value::from_json(br#"{"name":"Alice","tags":[1,-2,3.5,true,null]}"#).unwrap();
+///
+/// assert_eq!(val, logkit::Value::Object(vec![
+///     ("name".into(), logkit::Value::Str("Alice".into())),
+///     ("tags".into(), logkit::Value::Array(vec![
+///         logkit::Value::Uint(1),
+///         logkit::Value::Int(-2),
+///         logkit::Value::Float(3.5),
+///         logkit::Value::Bool(true),
+///         logkit::Value::Null,
+///     ])),
+/// ]));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    /// null
+    Null,
+
+    /// true or false
+    Bool(bool),
+
+    /// negative integer
+    Int(i64),
+
+    /// non-negative integer
+    Uint(u64),
+
+    /// floating point number
+    Float(f64),
+
+    /// integer beyond 64 bits, kept as decimal digits
+    BigInt(Cow<'a, str>),
+
+    /// string
+    Str(Cow<'a, str>),
+
+    /// array of values
+    Array(Vec<Value<'a>>),
+
+    /// object with ordered keys
+    Object(Vec<(Cow<'a, str>, Value<'a>)>),
+
+    /// JSON written as is, other formats parse it first
+    Raw(Cow<'a, str>),
+}
+
+impl<'a> Value<'a> {
+    /// Parse a JSON document
+    ///
+    /// Returns `None` if the input is not valid JSON.
+    ///
+    /// ```
+    /// assert_eq!(logkit::Value::from_json(br#""a\nb""#), Some(logkit::Value::Str("a\nb".into())));
+    /// assert_eq!(logkit::Value::from_json(b"340282366920938463463374607431768211455"), Some(logkit::Value::BigInt("340282366920938463463374607431768211455".into())));
+    /// assert_eq!(logkit::Value::from_json(b"{"), None);
+    /// assert_eq!(logkit::Value::from_json(b"-"), None);
+    /// assert_eq!(logkit::Value::from_json(b"--5"), None);
+    /// assert_eq!(logkit::Value::from_json(b"-5e+3"), Some(logkit::Value::Float(-5000.0)));
+    /// ```
+    pub fn from_json(json: &'a [u8]) -> Option<Self> {
+        let mut parser = Parser {json, pos: 0};
+        let value = parser.value()?;
+
+        parser.space();
+
+        match parser.pos == json.len() {
+            true => Some(value),
+            false => None,
+        }
+    }
+
+    /// Get the string if the value is a string
+    ///
+    /// ```
+    /// assert_eq!(logkit::Value::Str("hello".into()).as_str(), Some("hello"));
+    /// assert_eq!(logkit::Value::Uint(1).as_str(), None);
+    /// ```
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Convert the value to a float if it is a number
+    ///
+    /// ```
+    /// assert_eq!(logkit::Value::Int(-1).as_f64(), Some(-1.0));
+    /// assert_eq!(logkit::Value::Str("1".into()).as_f64(), None);
+    /// ```
+    #[inline]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(val) => Some(*val as f64),
+            Value::Uint(val) => Some(*val as f64),
+            Value::Float(val) => Some(*val),
+            Value::BigInt(val) => val.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Visit every scalar inside the value, nested keys and array indexes are joined by dots
+    ///
+    /// Empty arrays and objects are visited as they are.
+    ///
+    /// ```
+    /// let val = logkit::Value::from_json(br#"{"method":"GET","ports":[80,443],"extra":{}}"#).unwrap();
+    /// let mut keys = vec![];
+    /// val.flatten("http", |key, _| keys.push(key.to_string()));
+    /// assert_eq!(keys, vec!["http.method", "http.ports.0", "http.ports.1", "http.extra"]);
+    /// ```
+    pub fn flatten(&self, key: &str, mut visit: impl FnMut(&str, &Value)) {
+        let mut path = key.to_string();
+        self.flatten_into(&mut path, &mut visit);
+    }
+
+    fn flatten_into(&self, path: &mut String, visit: &mut impl FnMut(&str, &Value)) {
+        let len = path.len();
+
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push('.');
+                    path.push_str(&i.to_string());
+                    item.flatten_into(path, visit);
+                    path.truncate(len);
+                }
+            }
+            Value::Object(items) if !items.is_empty() => {
+                for (key, item) in items {
+                    path.push('.');
+                    path.push_str(key);
+                    item.flatten_into(path, visit);
+                    path.truncate(len);
+                }
+            }
+            Value::Raw(json) => match Value::from_json(json.as_bytes()) {
+                Some(val) => val.flatten_into(path, visit),
+                None => visit(path, &Value::Str(json.as_ref().into())),
+            },
+            _ => visit(path, self),
+        }
+    }
+
+    /// Insert a value into an object, keys joined by dots are nested into inner objects
+    ///
+    /// An existing value with the same key is replaced. Nothing happens if self is not an object.
+    ///
+    /// ```
+    /// let mut val = logkit::Value::Object(vec![]);
+    /// val.insert_nested("log.level", logkit::Value::Str("info".into()));
+    /// val.insert_nested("log.origin.file.line", logkit::Value::Uint(9));
+    /// val.insert_nested("message", logkit::Value::Str("hello".into()));
+    ///
+    /// let mut buf = vec![];
+    /// logkit::Encode::encode(&val, &mut buf);
+    /// assert_eq!(String::from_utf8_lossy(&buf), r#"{"log":{"level":"info","origin":{"file":{"line":9}}},"message":"hello"}"#);
+    /// ```
+    pub fn insert_nested(&mut self, path: &str, value: Value<'a>) {
+        let Value::Object(items) = self else {
+            return;
+        };
+
+        let (head, rest) = match path.split_once('.') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (path, None),
+        };
+
+        let index = match items.iter().position(|(key, _)| key == head) {
+            Some(index) => index,
+            None => {
+                items.push((Cow::Owned(head.to_string()), Value::Null));
+                items.len() - 1
+            }
+        };
+
+        match rest {
+            None => items[index].1 = value,
+            Some(rest) => {
+                if !matches!(items[index].1, Value::Object(_)) {
+                    items[index].1 = Value::Object(vec![]);
+                }
+
+                items[index].1.insert_nested(rest, value);
+            }
+        }
+    }
+
+    /// Copy all borrowed data to get an owned value
+    ///
+    /// ```
+    /// let json = br#"["hello"]"#.to_vec();
+    /// let value = logkit::Value::from_json(&json).unwrap().into_owned();
+    /// drop(json);
+    /// assert_eq!(value, logkit::Value::Array(vec![logkit::Value::Str("hello".into())]));
+    /// ```
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::Null => Value::Null,
+            Value::Bool(val) => Value::Bool(val),
+            Value::Int(val) => Value::Int(val),
+            Value::Uint(val) => Value::Uint(val),
+            Value::Float(val) => Value::Float(val),
+            Value::BigInt(val) => Value::BigInt(Cow::Owned(val.into_owned())),
+            Value::Str(val) => Value::Str(Cow::Owned(val.into_owned())),
+            Value::Array(val) => Value::Array(val.into_iter().map(Value::into_owned).collect()),
+            Value::Object(val) => Value::Object(val.into_iter().map(|(k, v)| (Cow::Owned(k.into_owned()), v.into_owned())).collect()),
+            Value::Raw(val) => Value::Raw(Cow::Owned(val.into_owned())),
+        }
+    }
+
+    // whether the value borrows nothing, so it can be kept without copying
+    pub(crate) fn is_owned(&self) -> bool {
+        match self {
+            Value::BigInt(val) | Value::Str(val) | Value::Raw(val) => matches!(val, Cow::Owned(_)),
+            Value::Array(items) => items.iter().all(Value::is_owned),
+            Value::Object(items) => items.iter().all(|(key, val)| matches!(key, Cow::Owned(_)) && val.is_owned()),
+            _ => true,
+        }
+    }
+
+    // turn invalid raw JSON into strings, so the output stays valid JSON
+    pub(crate) fn validate(&mut self) {
+        match self {
+            Value::Raw(json) if Value::from_json(json.as_bytes()).is_none() => *self = Value::Str(std::mem::take(json)),
+            Value::Array(items) => items.iter_mut().for_each(Value::validate),
+            Value::Object(items) => items.iter_mut().for_each(|(_, val)| val.validate()),
+            _ => {}
+        }
+    }
+
+    /// Parse the JSON of a raw value, other values are borrowed as they are
+    ///
+    /// An invalid raw value is treated as a string.
+    ///
+    /// ```
+    /// let val = logkit::Value::Raw(r#"{"id":1}"#.into());
+    /// assert_eq!(val.parsed().into_owned(), logkit::Value::Object(vec![("id".into(), logkit::Value::Uint(1))]));
+    /// ```
+    pub fn parsed(&self) -> Cow<'_, Value<'_>> {
+        match self {
+            Value::Raw(json) => Cow::Owned(Value::from_json(json.as_bytes()).unwrap_or_else(|| Value::Str(json.as_ref().into()))),
+            _ => Cow::Borrowed(self),
+        }
+    }
+}
+
+impl From<bool> for Value<'_> {
+    #[inline]
+    fn from(val: bool) -> Self {
+        Value::Bool(val)
+    }
+}
+
+macro_rules! value_from_int {
+    ($($ty:ty),+) => {$(
+        impl From<$ty> for Value<'_> {
+            #[inline]
+            fn from(val: $ty) -> Self {
+                match u64::try_from(val) {
+                    Ok(val) => Value::Uint(val),
+                    Err(_) => Value::Int(val as i64),
+                }
+            }
+        }
+    )+};
+}
+
+value_from_int!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl From<f32> for Value<'_> {
+    #[inline]
+    fn from(val: f32) -> Self {
+        Value::Float(val as f64)
+    }
+}
+
+impl From<f64> for Value<'_> {
+    #[inline]
+    fn from(val: f64) -> Self {
+        Value::Float(val)
+    }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    #[inline]
+    fn from(val: &'a str) -> Self {
+        Value::Str(Cow::Borrowed(val))
+    }
+}
+
+impl From<String> for Value<'_> {
+    #[inline]
+    fn from(val: String) -> Self {
+        Value::Str(Cow::Owned(val))
+    }
+}
+
+impl<'a> From<Vec<Value<'a>>> for Value<'a> {
+    #[inline]
+    fn from(val: Vec<Value<'a>>) -> Self {
+        Value::Array(val)
+    }
+}
+
+impl Encode for Value<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::Null => buf.extend_from_slice(b"null"),
+            Value::Bool(val) => val.encode(buf),
+            Value::Int(val) => val.encode(buf),
+            Value::Uint(val) => val.encode(buf),
+            Value::Float(val) => val.encode(buf),
+            Value::BigInt(val) => buf.extend_from_slice(val.as_bytes()),
+            Value::Str(val) => val.as_ref().encode(buf),
+            Value::Array(val) => {
+                buf.push(b'[');
+
+                for (i, item) in val.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b',');
+                    }
+
+                    item.encode(buf);
+                }
+
+                buf.push(b']');
+            }
+            Value::Object(val) => {
+                buf.push(b'{');
+
+                for (i, (key, item)) in val.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b',');
+                    }
+
+                    key.as_ref().encode(buf);
+                    buf.push(b':');
+                    item.encode(buf);
+                }
+
+                buf.push(b'}');
+            }
+            Value::Raw(val) => buf.extend_from_slice(val.as_bytes()),
+        }
+    }
+}
+
+/// Decode a JSON string literal, including the quotes
+///
+/// ```
+/// assert_eq!(logkit::value::decode_str(br#""plain""#).as_deref(), Some("plain"));
+/// assert_eq!(logkit::value::decode_str(br#""tab\t\u00e9\ud83d\ude00""#).as_deref(), Some("tab\té😀"));
+/// ```
+pub fn decode_str(json: &[u8]) -> Option<Cow<'_, str>> {
+    let mut parser = Parser {json, pos: 0};
+    let value = parser.string()?;
+
+    match parser.pos == json.len() {
+        true => Some(value),
+        false => None,
+    }
+}
+
+struct Parser<'a> {
+    json: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.json.get(self.pos).copied()
+    }
+
+    #[inline]
+    fn space(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    #[inline]
+    fn expect(&mut self, word: &[u8]) -> Option<()> {
+        match self.json[self.pos..].starts_with(word) {
+            true => { self.pos += word.len(); Some(()) }
+            false => None,
+        }
+    }
+
+    fn value(&mut self) -> Option<Value<'a>> {
+        self.space();
+
+        match self.peek()? {
+            b'n' => self.expect(b"null").map(|_| Value::Null),
+            b't' => self.expect(b"true").map(|_| Value::Bool(true)),
+            b'f' => self.expect(b"false").map(|_| Value::Bool(false)),
+            b'"' => self.string().map(Value::Str),
+            b'[' => self.array(),
+            b'{' => self.object(),
+            _ => self.number(),
+        }
+    }
+
+    fn array(&mut self) -> Option<Value<'a>> {
+        let mut items = vec![];
+
+        self.pos += 1;
+        self.space();
+
+        if self.peek()? == b']' {
+            self.pos += 1;
+            return Some(Value::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.space();
+
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b']' => { self.pos += 1; return Some(Value::Array(items)); }
+                _ => return None,
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Value<'a>> {
+        let mut items = vec![];
+
+        self.pos += 1;
+        self.space();
+
+        if self.peek()? == b'}' {
+            self.pos += 1;
+            return Some(Value::Object(items));
+        }
+
+        loop {
+            self.space();
+            let key = self.string()?;
+            self.space();
+            self.expect(b":")?;
+            items.push((key, self.value()?));
+            self.space();
+
+            match self.peek()? {
+                b',' => self.pos += 1,
+                b'}' => { self.pos += 1; return Some(Value::Object(items)); }
+                _ => return None,
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<Value<'a>> {
+        let beg = self.pos;
+        let mut float = false;
+
+        while let Some(ch) = self.peek() {
+            match ch {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => float = true,
+                _ => break,
+            }
+
+            self.pos += 1;
+        }
+
+        let text = std::str::from_utf8(&self.json[beg..self.pos]).ok()?;
+
+        if !json_number(text.as_bytes()) {
+            return None;
+        }
+
+        if float {
+            return text.parse().ok().map(Value::Float);
+        }
+
+        if let Ok(val) = text.parse::<u64>() {
+            return Some(Value::Uint(val));
+        }
+
+        if let Ok(val) = text.parse::<i64>() {
+            return Some(Value::Int(val));
+        }
+
+        Some(Value::BigInt(Cow::Borrowed(text)))
+    }
+
+    fn string(&mut self) -> Option<Cow<'a, str>> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+
+        self.pos += 1;
+        let beg = self.pos;
+
+        // fast path for strings without escapes
+        while let Some(ch) = self.peek() {
+            match ch {
+                b'"' => {
+                    self.pos += 1;
+                    return std::str::from_utf8(&self.json[beg..self.pos - 1]).ok().map(Cow::Borrowed);
+                }
+                b'\\' => break,
+                _ => self.pos += 1,
+            }
+        }
+
+        let mut out = self.json[beg..self.pos].to_vec();
+
+        loop {
+            match self.peek()? {
+                b'"' => {
+                    self.pos += 1;
+                    return String::from_utf8(out).ok().map(Cow::Owned);
+                }
+                b'\\' => {
+                    self.pos += 1;
+
+                    let ch = match self.peek()? {
+                        b'u' => self.unicode()?,
+                        other => {
+                            self.pos += 1;
+
+                            match other {
+                                b'"' => '"',
+                                b'\\' => '\\',
+                                b'/' => '/',
+                                b'b' => '\x08',
+                                b'f' => '\x0c',
+                                b'n' => '\n',
+                                b'r' => '\r',
+                                b't' => '\t',
+                                _ => return None,
+                            }
+                        }
+                    };
+
+                    out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                ch => {
+                    self.pos += 1;
+                    out.push(ch);
+                }
+            }
+        }
+    }
+
+    fn unicode(&mut self) -> Option<char> {
+        let mut code = self.hex()?;
+
+        // surrogate pair
+        if (0xD800..0xDC00).contains(&code) {
+            self.expect(b"\\")?;
+
+            let low = self.hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return None;
+            }
+
+            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+        }
+
+        char::from_u32(code)
+    }
+
+    fn hex(&mut self) -> Option<u32> {
+        self.expect(b"u")?;
+        let text = std::str::from_utf8(self.json.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(text, 16).ok()
+    }
+}
+
+// check the number grammar of JSON: -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?
+fn json_number(text: &[u8]) -> bool {
+    let digits = |pos: usize| pos + text[pos..].iter().take_while(|ch| ch.is_ascii_digit()).count();
+
+    let mut pos = usize::from(text.first() == Some(&b'-'));
+    let end = digits(pos);
+
+    if end == pos || (text[pos] == b'0' && end - pos > 1) {
+        return false;
+    }
+
+    pos = end;
+
+    if text.get(pos) == Some(&b'.') {
+        let end = digits(pos + 1);
+        if end == pos + 1 {
+            return false;
+        }
+        pos = end;
+    }
+
+    if let Some(b'e' | b'E') = text.get(pos) {
+        pos += 1;
+        if let Some(b'+' | b'-') = text.get(pos) {
+            pos += 1;
+        }
+        let end = digits(pos);
+        if end == pos {
+            return false;
+        }
+        pos = end;
+    }
+
+    pos == text.len()
+}